@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use barnes_hut::initial_conditions::unit_cube;
+use barnes_hut::linear::LinearOctree;
+use barnes_hut::octtree::{Octree, OctreeOptions};
+
+fn bench_force_evaluation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Force Evaluation");
+    group.measurement_time(Duration::from_secs(30));
+    group.sample_size(10);
+
+    for size in (10usize..=18usize).step_by(2).map(|v| 1usize << v) {
+        group.throughput(criterion::Throughput::Elements(size as u64));
+
+        let points = unit_cube::<f32>(size, &mut rand::thread_rng());
+        let masses = vec![1f32; size];
+        let tree = Octree::construct_par(&points, &masses);
+
+        group.bench_with_input(BenchmarkId::new("f32 serial", size), &size, |b, _| {
+            b.iter(|| {
+                black_box(&points)
+                    .iter()
+                    .map(|&point| tree.acceleration(point, 0.5, 1e-3))
+                    .collect::<Vec<_>>()
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("f32 parallel", size), &size, |b, _| {
+            b.iter(|| tree.accelerations(black_box(&points), 0.5, 1e-3));
+        });
+
+        let linear = LinearOctree::construct(&points, &masses);
+        group.bench_with_input(BenchmarkId::new("f32 linear", size), &size, |b, _| {
+            b.iter(|| linear.accelerations(black_box(&points), 0.5, 1e-3));
+        });
+
+        for bucket_lanes in [1, 2, 4] {
+            let options = OctreeOptions {
+                bucket_lanes,
+                leaf_only: true,
+                ..Default::default()
+            };
+            let tree = Octree::construct_par_with(&points, &masses, options);
+            let id = format!("f32 leaf-only {}", options.bucket_size());
+            group.bench_with_input(BenchmarkId::new(id, size), &size, |b, _| {
+                b.iter(|| tree.accelerations(black_box(&points), 0.5, 1e-3));
+            });
+
+            let id = format!("f32 fmm {}", options.bucket_size());
+            group.bench_with_input(BenchmarkId::new(id, size), &size, |b, _| {
+                b.iter(|| black_box(&tree).fmm_fields(0.5, 1e-3));
+            });
+
+            let id = format!("f32 group {}", options.bucket_size());
+            group.bench_with_input(BenchmarkId::new(id, size), &size, |b, _| {
+                b.iter(|| black_box(&tree).group_fields(0.5, 1e-3));
+            });
+        }
+
+        let points = unit_cube::<f64>(size, &mut rand::thread_rng());
+        let masses = vec![1f64; size];
+        let tree = Octree::construct_par(&points, &masses);
+
+        group.bench_with_input(BenchmarkId::new("f64 parallel", size), &size, |b, _| {
+            b.iter(|| tree.accelerations(black_box(&points), 0.5, 1e-3));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_force_evaluation);
+criterion_main!(benches);
@@ -1,10 +1,11 @@
 use std::time::Duration;
 
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
-use rand::{prelude::*, Fill};
-use ultraviolet::Vec3;
 
+use barnes_hut::initial_conditions::unit_cube;
+use barnes_hut::linear::LinearOctree;
 use barnes_hut::octtree::Octree;
+
 fn bench_octree_construction(c: &mut Criterion) {
     let mut group = c.benchmark_group("Octree Construction");
     group.measurement_time(Duration::from_secs(60));
@@ -12,25 +13,29 @@ fn bench_octree_construction(c: &mut Criterion) {
     for size in (4usize..=20usize).map(|v| 1usize << v) {
         group.throughput(criterion::Throughput::Elements(size as u64));
 
-        group.bench_with_input(
-            BenchmarkId::new("Octree Construction", size),
-            &size,
-            |b, &size| {
-                let mut points: Vec<Vec3> = vec![Vec3::zero(); size];
-                let mut masses: Vec<f32> = vec![0.; size];
-
-                points
-                    .iter_mut()
-                    .map(Vec3::as_mut_slice)
-                    .for_each(|s| s.copy_from_slice(&rand::thread_rng().gen::<[f32; 3]>()));
-
-                masses
-                    .try_fill(&mut rand::thread_rng())
-                    .expect("Failed to fill masses");
-
-                b.iter(|| Octree::construct(black_box(&points), black_box(&masses)));
-            },
-        );
+        let points = unit_cube::<f32>(size, &mut rand::thread_rng());
+        let masses = vec![1f32; size];
+        group.bench_with_input(BenchmarkId::new("f32 serial", size), &size, |b, _| {
+            b.iter(|| Octree::construct(black_box(&points), black_box(&masses)));
+        });
+        group.bench_with_input(BenchmarkId::new("f32 parallel", size), &size, |b, _| {
+            b.iter(|| Octree::construct_par(black_box(&points), black_box(&masses)));
+        });
+        group.bench_with_input(BenchmarkId::new("f32 linear", size), &size, |b, _| {
+            b.iter(|| LinearOctree::construct(black_box(&points), black_box(&masses)));
+        });
+
+        let points = unit_cube::<f64>(size, &mut rand::thread_rng());
+        let masses = vec![1f64; size];
+        group.bench_with_input(BenchmarkId::new("f64 serial", size), &size, |b, _| {
+            b.iter(|| Octree::construct(black_box(&points), black_box(&masses)));
+        });
+        group.bench_with_input(BenchmarkId::new("f64 parallel", size), &size, |b, _| {
+            b.iter(|| Octree::construct_par(black_box(&points), black_box(&masses)));
+        });
+        group.bench_with_input(BenchmarkId::new("f64 linear", size), &size, |b, _| {
+            b.iter(|| LinearOctree::construct(black_box(&points), black_box(&masses)));
+        });
     }
 
     group.finish();
@@ -0,0 +1,305 @@
+use rayon::prelude::*;
+
+use crate::{
+    kernel::{Gravity, Kernel},
+    real::{Real, Vector, Wide, WideVector},
+    softening::Softening,
+};
+
+/// Exact `O(N^2)` accelerations (with `G = 1`) at each of `points` from all of `points`.
+pub fn accelerations<S: Real>(points: &[S::Vector], masses: &[S], softening: S) -> Vec<S::Vector> {
+    fields(points, masses, softening)
+        .into_iter()
+        .map(|(acc, _)| acc)
+        .collect()
+}
+
+/// Exact `O(N^2)` potentials at each of `points`, see [`accelerations`].
+pub fn potentials<S: Real>(points: &[S::Vector], masses: &[S], softening: S) -> Vec<S> {
+    fields(points, masses, softening)
+        .into_iter()
+        .map(|(_, pot)| pot)
+        .collect()
+}
+
+/// Exact `O(N^2)` accelerations and potentials at each of `points`, evaluated in parallel, with
+/// Plummer softening length `softening`.
+pub fn fields<S: Real>(points: &[S::Vector], masses: &[S], softening: S) -> Vec<(S::Vector, S)> {
+    fields_with(
+        points,
+        masses,
+        &vec![softening; points.len()],
+        Softening::Plummer,
+    )
+}
+
+/// Exact `O(N^2)` accelerations and potentials at each of `points` with the softening `kernel`,
+/// where each pair of points is softened with the larger of their lengths in `softenings`.
+pub fn fields_with<S: Real>(
+    points: &[S::Vector],
+    masses: &[S],
+    softenings: &[S],
+    kernel: Softening,
+) -> Vec<(S::Vector, S)> {
+    fields_with_kernel(points, masses, softenings, &Gravity(kernel))
+}
+
+/// Exact `O(N^2)` accelerations and potentials at each of `points` under any [`Kernel`], with
+/// `strengths` as the source strengths, see [`fields_with`].
+pub fn fields_with_kernel<S: Real, K: Kernel<S>>(
+    points: &[S::Vector],
+    strengths: &[S],
+    softenings: &[S],
+    kernel: &K,
+) -> Vec<(S::Vector, S)> {
+    check_lengths(points, strengths, softenings);
+    let lanes = pack(points, strengths, softenings);
+
+    points
+        .par_iter()
+        .zip(softenings)
+        .map(|(&point, &softening)| lanes_field(&lanes, point, softening, kernel))
+        .collect()
+}
+
+/// Exact `O(N)` accelerations and potentials at only the points with indices in `targets`, from
+/// all of `points`, see [`fields_with`].
+pub fn fields_of<S: Real>(
+    points: &[S::Vector],
+    masses: &[S],
+    softenings: &[S],
+    kernel: Softening,
+    targets: &[usize],
+) -> Vec<(S::Vector, S)> {
+    check_lengths(points, masses, softenings);
+    let (lanes, kernel) = (pack(points, masses, softenings), Gravity(kernel));
+
+    targets
+        .par_iter()
+        .map(|&i| lanes_field(&lanes, points[i], softenings[i], &kernel))
+        .collect()
+}
+
+fn check_lengths<S: Real>(points: &[S::Vector], masses: &[S], softenings: &[S]) {
+    assert_eq!(
+        points.len(),
+        masses.len(),
+        "Length of given points not equal to length of given masses"
+    );
+    assert_eq!(
+        points.len(),
+        softenings.len(),
+        "Length of given points not equal to length of given softenings"
+    );
+}
+
+/// Acceleration and potential at `point` from all the packed `lanes`, see [`lane_field`].
+fn lanes_field<S: Real, K: Kernel<S>>(
+    lanes: &[(S::WideVector, S::Wide, S::Wide)],
+    point: S::Vector,
+    softening: S,
+    kernel: &K,
+) -> (S::Vector, S) {
+    lanes.iter().fold(
+        (S::Vector::zero(), S::ZERO),
+        |acc, &(positions, masses, softenings)| {
+            let (a, p) = lane_field(positions, masses, softenings, point, softening, kernel);
+            (acc.0 + a, acc.1 + p)
+        },
+    )
+}
+
+/// Packs `points`, `masses` and `softenings` into SIMD lanes, padding the last lane with zero
+/// mass at the origin.
+pub(crate) fn pack<S: Real>(
+    points: &[S::Vector],
+    masses: &[S],
+    softenings: &[S],
+) -> Vec<(S::WideVector, S::Wide, S::Wide)> {
+    (0..points.len())
+        .step_by(S::LANES)
+        .map(|start| {
+            let mut xyz = [S::Array::default(); 3];
+            let mut mass = S::Array::default();
+            let mut softening = S::Array::default();
+
+            (start..points.len().min(start + S::LANES)).for_each(|i| {
+                let slot = i - start;
+                (0..3).for_each(|axis| xyz[axis].as_mut()[slot] = points[i][axis]);
+                mass.as_mut()[slot] = masses[i];
+                softening.as_mut()[slot] = softenings[i];
+            });
+
+            let [x, y, z] = xyz.map(S::Wide::new);
+            let pos = S::WideVector::new(x, y, z);
+            (pos, S::Wide::new(mass), S::Wide::new(softening))
+        })
+        .collect()
+}
+
+/// Acceleration and potential at `point`, with softening length `softening`, from
+/// [`Real::LANES`] sources, each pair interacting by `kernel` with the larger of the two
+/// softening lengths. Sources at zero distance, which includes `point` itself and zero-mass
+/// padding lanes at the origin, do not contribute.
+pub(crate) fn lane_field<S: Real, K: Kernel<S>>(
+    positions: S::WideVector,
+    masses: S::Wide,
+    softenings: S::Wide,
+    point: S::Vector,
+    softening: S,
+    kernel: &K,
+) -> (S::Vector, S) {
+    let zero = S::Wide::splat(S::ZERO);
+
+    let diff = positions - S::WideVector::splat(point);
+    let dist_sq = diff.mag_sq();
+    let softening = softenings.max(S::Wide::splat(softening));
+    let (phi, f) = kernel.lane_factors(dist_sq, softening);
+
+    let self_mask = dist_sq.cmp_eq(zero);
+    let pot = self_mask.blend(zero, masses * phi);
+    let acc = diff * self_mask.blend(zero, masses * f);
+
+    (acc.reduce_add(), pot.reduce_add())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accelerations, fields, fields_with, potentials};
+    use crate::{
+        linear::LinearOctree,
+        multipole::Multipole,
+        octtree::{Octree, OctreeOptions},
+        softening::Softening,
+        test_helpers::{clustered, errors, masses, uniform},
+    };
+    use rand::prelude::*;
+    use ultraviolet::Vec3;
+
+    const SOFTENING: f32 = 1e-3;
+
+    /// Mean relative error of the tree accelerations at `points`.
+    fn tree_error(points: &[Vec3], masses: &[f32], theta: f32, multipole: Multipole) -> f32 {
+        let options = OctreeOptions {
+            multipole,
+            ..Default::default()
+        };
+        let tree = Octree::construct_with(points, masses, options);
+        let exact = fields(points, masses, SOFTENING);
+        errors(&exact, &tree.fields(points, theta, SOFTENING)).0
+    }
+
+    fn check_error_scaling(points: &[Vec3], masses: &[f32]) {
+        let errors: Vec<f32> = [0., 0.25, 0.5, 0.75, 1.]
+            .iter()
+            .map(|&theta| tree_error(points, masses, theta, Multipole::Monopole))
+            .collect();
+
+        assert!(errors[0] < 1e-4, "theta = 0 should be exact: {:?}", errors);
+        assert!(errors[2] < 5e-3, "theta = 0.5 too inaccurate: {:?}", errors);
+        assert!(errors[4] < 5e-2, "theta = 1 too inaccurate: {:?}", errors);
+        assert!(
+            errors[1] < errors[4],
+            "error should grow with theta: {:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_tree_error_uniform() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let points = uniform(1000, &mut rng);
+        let masses = masses(points.len(), &mut rng);
+
+        check_error_scaling(&points, &masses);
+    }
+
+    #[test]
+    fn test_tree_error_clustered() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let points = clustered(1000, &mut rng);
+        let masses = vec![1f32; points.len()];
+
+        check_error_scaling(&points, &masses);
+    }
+
+    #[test]
+    fn test_quadrupole_error() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let points = clustered(1000, &mut rng);
+        let masses = vec![1f32; points.len()];
+
+        let error = |theta, multipole| tree_error(&points, &masses, theta, multipole);
+        let monopole = [0.5, 0.75, 1.].map(|theta| error(theta, Multipole::Monopole));
+        let quadrupole = [0.5, 0.75, 1.].map(|theta| error(theta, Multipole::Quadrupole));
+
+        monopole
+            .iter()
+            .zip(&quadrupole)
+            .for_each(|(m, q)| assert!(q < m, "{:?} {:?}", monopole, quadrupole));
+
+        // Comparable accuracy at a larger opening angle.
+        assert!(
+            quadrupole[1] < 2. * monopole[0],
+            "{:?} {:?}",
+            monopole,
+            quadrupole
+        );
+    }
+
+    #[test]
+    fn test_direct_pair() {
+        let points = [Vec3::zero(), Vec3::new(2., 0., 0.)];
+        let masses = [1f32, 3.];
+
+        let acc = accelerations(&points, &masses, 0.);
+        let pot = potentials(&points, &masses, 0.);
+
+        assert_eq!(acc, vec![Vec3::new(0.75, 0., 0.), Vec3::new(-0.25, 0., 0.)]);
+        assert_eq!(pot, vec![-1.5, -0.5]);
+    }
+
+    #[test]
+    fn test_softening_kernels_match_direct() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let points = clustered(1000, &mut rng);
+        let masses = vec![1f32; points.len()];
+        let softenings: Vec<f32> = (0..points.len())
+            .map(|_| rng.gen_range(0.0..0.05))
+            .collect();
+
+        for kernel in [Softening::None, Softening::Plummer, Softening::Spline] {
+            let exact = fields_with(&points, &masses, &softenings, kernel);
+            let options = OctreeOptions {
+                softening: kernel,
+                ..Default::default()
+            };
+            let mut tree = Octree::construct_with(&points, &masses, options);
+            tree.set_softenings(&softenings);
+            let mut linear = LinearOctree::construct_with(&points, &masses, options);
+            linear.set_softenings(&softenings);
+
+            let error = |theta: f32, linear_walk: bool| {
+                points
+                    .iter()
+                    .zip(&softenings)
+                    .zip(&exact)
+                    .map(|((&point, &softening), &(acc, _))| {
+                        let approx = match linear_walk {
+                            true => linear.acceleration(point, theta, softening),
+                            false => tree.acceleration(point, theta, softening),
+                        };
+                        (approx - acc).mag() / acc.mag()
+                    })
+                    .sum::<f32>()
+                    / points.len() as f32
+            };
+
+            for linear_walk in [false, true] {
+                let (opened, approx) = (error(0., linear_walk), error(0.5, linear_walk));
+                assert!(opened < 1e-4, "{:?} {} {}", kernel, linear_walk, opened);
+                assert!(approx < 1e-2, "{:?} {} {}", kernel, linear_walk, approx);
+            }
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::{f64::consts::PI, sync::OnceLock};
+
+use rayon::prelude::*;
+
+use crate::real::{Real, Vector};
+
+/// Intervals of the correction table along each axis, spanning half of the box.
+const TABLE_INTERVALS: usize = 32;
+
+/// Splitting parameter of the Ewald sum in units of the inverse box size.
+const ALPHA: f64 = 2.;
+
+/// Periodic images summed in real space, `-IMAGES..=IMAGES` along each axis.
+const IMAGES: i32 = 2;
+
+/// Reciprocal lattice vectors `h` summed in Fourier space, those with `|h|^2 <= WAVES^2`.
+const WAVES: i32 = 4;
+
+/// Correction to the acceleration and potential of a unit mass, from its periodic images and the
+/// uniform background cancelling the mean density, on a grid over the positive octant of half of
+/// the unit box.
+static TABLE: OnceLock<Vec<[f64; 4]>> = OnceLock::new();
+
+/// Ewald correction for a source of unit mass at displacement `d` from the target, in a periodic
+/// box of size `box_size`, interpolated from a table computed on first use. Added to the
+/// Newtonian acceleration `d / r^3` and potential `-1 / r` of the nearest image, this gives the
+/// field of the source and all its periodic images, with a uniform background of negative mass
+/// cancelling their mean density. `d` must be the nearest image, within half of the box along
+/// every axis.
+pub fn correction<S: Real>(d: S::Vector, box_size: S) -> (S::Vector, S) {
+    let n = TABLE_INTERVALS;
+    let table = TABLE.get_or_init(table);
+
+    let l = box_size.to_f64();
+    let u = [0, 1, 2].map(|axis| (d[axis].to_f64() / l).abs() * (2 * n) as f64);
+    let cell = u.map(|u| (u.floor() as usize).min(n - 1));
+    let t = [0, 1, 2].map(|axis| u[axis] - cell[axis] as f64);
+
+    // Trilinear interpolation between the corners of the table cell.
+    let mut value = [0.; 4];
+    (0..8).for_each(|corner| {
+        let offset = [corner >> 2 & 1, corner >> 1 & 1, corner & 1];
+        let weight: f64 = (0..3)
+            .map(|axis| match offset[axis] {
+                0 => 1. - t[axis],
+                _ => t[axis],
+            })
+            .product();
+        let entry = table[index([0, 1, 2].map(|axis| cell[axis] + offset[axis]))];
+        (0..4).for_each(|c| value[c] += weight * entry[c]);
+    });
+
+    // The correction is odd along each axis of the acceleration, and even in the potential.
+    let acc = [0, 1, 2].map(|axis| match d[axis] < S::ZERO {
+        true => S::from_f64(-value[axis]),
+        false => S::from_f64(value[axis]),
+    });
+    (
+        S::Vector::new(acc[0], acc[1], acc[2]) / S::from_f64(l * l),
+        S::from_f64(value[3] / l),
+    )
+}
+
+/// Computes [`TABLE`]. Only grid points with descending coordinates are summed, and the others
+/// are filled in by the symmetry of the box under permutations of the axes.
+fn table() -> Vec<[f64; 4]> {
+    let n = TABLE_INTERVALS;
+    let sorted: Vec<[usize; 3]> = (0..=n)
+        .flat_map(|i| (0..=i).flat_map(move |j| (0..=j).map(move |k| [i, j, k])))
+        .collect();
+    let values: Vec<[f64; 4]> = sorted
+        .par_iter()
+        .map(|u| {
+            let (acc, pot) = exact_correction(u.map(|u| u as f64 / (2 * n) as f64), ALPHA);
+            [acc[0], acc[1], acc[2], pot]
+        })
+        .collect();
+
+    let mut table = vec![[0.; 4]; (n + 1).pow(3)];
+    sorted.iter().zip(&values).for_each(|(u, value)| {
+        let permutations = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+        permutations.iter().for_each(|p| {
+            table[index(p.map(|axis| u[axis]))] = [value[p[0]], value[p[1]], value[p[2]], value[3]];
+        });
+    });
+    table
+}
+
+/// Position in [`TABLE`] of the grid point `u`.
+fn index([i, j, k]: [usize; 3]) -> usize {
+    let n = TABLE_INTERVALS;
+    (i * (n + 1) + j) * (n + 1) + k
+}
+
+/// Exact accelerations and potentials at each of `points`, with positions in the periodic box
+/// `[0, box_size)^3`, from all other points and their periodic images, by direct Ewald summation
+/// over every pair. Unsoftened, and in `O(N^2)` time, so only suitable for small systems.
+pub fn fields<S: Real>(points: &[S::Vector], masses: &[S], box_size: S) -> Vec<(S::Vector, S)> {
+    assert_eq!(
+        points.len(),
+        masses.len(),
+        "Length of given points not equal to length of given masses"
+    );
+
+    let l = box_size.to_f64();
+    points
+        .par_iter()
+        .enumerate()
+        .map(|(i, &point)| {
+            let (mut acc, mut pot) = ([0.; 3], 0.);
+            points
+                .iter()
+                .zip(masses)
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .for_each(|(_, (&source, &mass))| {
+                    let d = [0, 1, 2].map(|axis| {
+                        let d = (source[axis] - point[axis]).to_f64() / l;
+                        d - d.round()
+                    });
+                    let (a, p) = exact_correction(d, ALPHA);
+                    let r = norm(d);
+                    let m = mass.to_f64();
+
+                    (0..3)
+                        .for_each(|axis| acc[axis] += m * (a[axis] + d[axis] / r.powi(3)) / l / l);
+                    pot += m * (p - 1. / r) / l;
+                });
+
+            let acc = acc.map(S::from_f64);
+            (S::Vector::new(acc[0], acc[1], acc[2]), S::from_f64(pot))
+        })
+        .collect()
+}
+
+/// Ewald sum minus the Newtonian field of the nearest image, for a unit mass at displacement `d`
+/// from the target in the unit box, with splitting parameter `alpha`. The nearest image is summed
+/// in a form which stays accurate as `d` goes to zero.
+fn exact_correction(d: [f64; 3], alpha: f64) -> ([f64; 3], f64) {
+    let (mut acc, mut pot) = ([0.; 3], PI / (alpha * alpha));
+
+    let r = norm(d);
+    let z = alpha * r;
+    // The nearest image less its Newtonian field, `-d / r^3 (erf z - 2 z / sqrt(pi) exp(-z^2))`
+    // and `erf z / r`.
+    let near = -4. / PI.sqrt() * alpha.powi(3) * series(z, 3);
+    (0..3).for_each(|axis| acc[axis] += near * d[axis]);
+    pot += 2. / PI.sqrt() * alpha * series(z, 1);
+
+    let range = || -IMAGES..=IMAGES;
+    range()
+        .flat_map(|i| range().flat_map(move |j| range().map(move |k| [i, j, k])))
+        .filter(|&n| n != [0; 3])
+        .for_each(|n| {
+            let x = [0, 1, 2].map(|axis| d[axis] + n[axis] as f64);
+            let r = norm(x);
+            let z = alpha * r;
+            let erfc = erfc(z);
+            let radial = erfc + 2. / PI.sqrt() * z * (-z * z).exp();
+
+            (0..3).for_each(|axis| acc[axis] += x[axis] / r.powi(3) * radial);
+            pot -= erfc / r;
+        });
+
+    // `exp(2 pi i h d)` is built up from the phases along each axis, and the weights only depend
+    // on `|h|^2`.
+    let [x_phases, y_phases, z_phases] = d.map(|d| {
+        (-WAVES..=WAVES)
+            .map(|h| (2. * PI * h as f64 * d).sin_cos())
+            .collect::<Vec<_>>()
+    });
+    let weights: Vec<f64> = (0..=WAVES * WAVES)
+        .map(|h_sq| (-PI * PI * h_sq as f64 / (alpha * alpha)).exp() / h_sq as f64)
+        .collect();
+
+    let range = || -WAVES..=WAVES;
+    range()
+        .flat_map(|i| range().flat_map(move |j| range().map(move |k| [i, j, k])))
+        .filter(|&h| h != [0; 3] && h.iter().map(|h| h * h).sum::<i32>() <= WAVES * WAVES)
+        .for_each(|h| {
+            let weight = weights[h.iter().map(|h| h * h).sum::<i32>() as usize];
+            let [(sx, cx), (sy, cy), (sz, cz)] = [
+                x_phases[(h[0] + WAVES) as usize],
+                y_phases[(h[1] + WAVES) as usize],
+                z_phases[(h[2] + WAVES) as usize],
+            ];
+            let (sxy, cxy) = (sx * cy + cx * sy, cx * cy - sx * sy);
+            let (sin, cos) = (sxy * cz + cxy * sz, cxy * cz - sxy * sz);
+
+            (0..3).for_each(|axis| acc[axis] += 2. * h[axis] as f64 * weight * sin);
+            pot -= weight * cos / PI;
+        });
+
+    (acc, pot)
+}
+
+/// `sum_k (-z^2)^k / (k! (2k + p))`, so `2 / sqrt(pi) z series(z, 1) = erf z`.
+fn series(z: f64, p: i32) -> f64 {
+    let (mut term, mut sum) = (1., 1. / p as f64);
+    for k in 1.. {
+        term *= -z * z / k as f64;
+        let next = term / (2 * k + p) as f64;
+        sum += next;
+        if next.abs() < 1e-17 * sum.abs() {
+            break;
+        }
+    }
+    sum
+}
+
+/// Complementary error function, from its Taylor series for small `z` and its continued
+/// fraction otherwise.
+fn erfc(z: f64) -> f64 {
+    if z < 2. {
+        1. - 2. / PI.sqrt() * z * series(z, 1)
+    } else {
+        let fraction = (1..=40).rev().fold(z, |f, k| z + k as f64 / 2. / f);
+        (-z * z).exp() / (PI.sqrt() * fraction)
+    }
+}
+
+fn norm(x: [f64; 3]) -> f64 {
+    x.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{correction, exact_correction, fields};
+    use crate::{
+        octtree::{Octree, OctreeOptions},
+        softening::Softening,
+    };
+    use rand::prelude::*;
+    use ultraviolet::{DVec3, Vec3};
+
+    #[test]
+    fn test_ewald_correction() {
+        // The Madelung constant of a simple cubic lattice with a neutralising background.
+        let (acc, pot) = exact_correction([0.; 3], 2.);
+        assert!(acc.iter().all(|a| a.abs() < 1e-12), "{:?}", acc);
+        assert!((pot - 2.837297).abs() < 1e-6, "{}", pot);
+
+        // Images half a box apart pull equally in opposite directions.
+        let (acc, _) = exact_correction([0.5, 0., 0.], 2.);
+        assert!(
+            (acc[0] + 4.).abs() < 1e-9 && acc[1].abs() < 1e-9,
+            "{:?}",
+            acc
+        );
+
+        // The sum does not depend on the split between real and Fourier space, and the table
+        // interpolates it closely.
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..100).for_each(|_| {
+            let d = [(); 3].map(|_| rng.gen_range(-0.5..0.5));
+            let (acc, pot) = exact_correction(d, 2.);
+            let (other_acc, other_pot) = exact_correction(d, 2.2);
+            let (table_acc, table_pot) = correction::<f64>(DVec3::from(d) * 3., 3.);
+
+            assert!((pot - other_pot).abs() < 1e-9, "{:?}", d);
+            assert!((pot / 3. - table_pot).abs() < 1e-3, "{:?}", d);
+            (0..3).for_each(|axis| {
+                assert!((acc[axis] - other_acc[axis]).abs() < 1e-9, "{:?}", d);
+                assert!((acc[axis] / 9. - table_acc[axis]).abs() < 1e-3, "{:?}", d);
+            });
+        });
+    }
+
+    #[test]
+    fn test_periodic_tree_matches_ewald_sum() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let box_size = 2f32;
+
+        // A perturbed lattice, with some points given outside the box to be wrapped into it.
+        let points: Vec<Vec3> = (0..64)
+            .map(|i| {
+                let cell = Vec3::new((i / 16) as f32, (i / 4 % 4) as f32, (i % 4) as f32);
+                let jitter = Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 0.3;
+                let shift = box_size * rng.gen_range(-1..=1) as f32;
+                (cell + jitter) * (box_size / 4.) + Vec3::broadcast(shift)
+            })
+            .collect();
+        let masses: Vec<f32> = (0..points.len()).map(|_| rng.gen_range(0.5..1.5)).collect();
+
+        let exact = fields(&points, &masses, box_size);
+        let options = OctreeOptions {
+            softening: Softening::None,
+            periodic: Some(box_size),
+            ..Default::default()
+        };
+        let tree = Octree::construct_with(&points, &masses, options);
+        tree.validate().unwrap();
+        assert_eq!(tree.extent(), Vec3::broadcast(box_size));
+
+        let error = |theta: f32| {
+            let approx = tree.fields(&points, theta, 0.);
+            exact
+                .iter()
+                .zip(&approx)
+                .map(|(e, a)| ((a.0 - e.0).mag() / e.0.mag(), (a.1 - e.1).abs() / e.1.abs()))
+                .fold((0f32, 0f32), |max, (acc, pot)| {
+                    (max.0.max(acc), max.1.max(pot))
+                })
+        };
+
+        let opened = error(0.);
+        assert!(opened.0 < 5e-3 && opened.1 < 5e-3, "{:?}", opened);
+        let approx = error(0.5);
+        assert!(approx.0 < 2e-2 && approx.1 < 1e-2, "{:?}", approx);
+    }
+}
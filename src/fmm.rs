@@ -0,0 +1,273 @@
+use std::ptr;
+
+use rayon::prelude::*;
+
+use crate::{
+    octtree::{scatter, Octree},
+    real::{Real, Vector},
+};
+
+/// Target nodes at least this deep descend into their children serially.
+const PARALLEL_DEPTH: u32 = 3;
+
+/// Second order local (Taylor) expansion of the field about the centre of a target node: the
+/// potential and acceleration at the centre, and the tidal tensor, the gradient of the
+/// acceleration.
+#[derive(Clone, Copy, Debug, Default)]
+struct Local<S: Real> {
+    pot: S,
+    acc: S::Vector,
+    /// Rows of the symmetric tidal tensor.
+    tidal: [S::Vector; 3],
+}
+
+impl<S: Real> Local<S> {
+    /// Adds the field of the well separated `source` node, expanded about `center` (the
+    /// multipole to local translation). The potential and acceleration include the quadrupole of
+    /// `source` if it has one, and the tidal tensor is that of its monopole, which is exact for
+    /// the Plummer kernel and for the spline kernel beyond its support.
+    fn add_cell(&mut self, source: &Octree<S>, center: S::Vector, softening: S) {
+        let (acc, pot) = source.cell_field(center, softening);
+        self.acc += acc;
+        self.pot += pot;
+
+        let d = source.com() - center;
+        let h = source.softening_length().max(softening);
+        let (inv_r, inv_r3) = source.options().softening.factors(d.mag_sq(), h);
+        let inv_r5 = inv_r3 * inv_r3 / inv_r;
+
+        let mass = source.total_mass();
+        let three = S::from_f64(3.);
+        (0..3).for_each(|i| {
+            let mut row = d * (three * d[i] * inv_r5);
+            row[i] -= inv_r3;
+            self.tidal[i] += row * mass;
+        });
+    }
+
+    /// The expansion moved to `offset` from its centre (the local to local translation).
+    fn shifted(&self, offset: S::Vector) -> Self {
+        let tidal_offset = self.tidal_apply(offset);
+
+        Self {
+            pot: self.pot - self.acc.dot(offset) - S::from_f64(0.5) * offset.dot(tidal_offset),
+            acc: self.acc + tidal_offset,
+            tidal: self.tidal,
+        }
+    }
+
+    fn tidal_apply(&self, v: S::Vector) -> S::Vector {
+        S::Vector::new(
+            self.tidal[0].dot(v),
+            self.tidal[1].dot(v),
+            self.tidal[2].dot(v),
+        )
+    }
+}
+
+impl<S: Real> Octree<S> {
+    /// Accelerations and potentials at every point in the tree, indexed as when the points were
+    /// inserted, with zeros at the indices of removed points, by the fast multipole method. This
+    /// costs `O(N)` rather than the `O(N log N)` of evaluating [`Octree::fields`] at the points of
+    /// the tree.
+    ///
+    /// Pairs of target and source nodes are visited together from the root down. A source node
+    /// is accepted as a whole for every point of a target node if `(s_t + s_s) / d < theta`,
+    /// where `s_t` and `s_s` are the sizes of the nodes and `d` is the distance from the centre
+    /// of the target to the centre of mass of the source. Its field is then added to a second
+    /// order local expansion about the centre of the target, which is passed down to the
+    /// children and evaluated at the points of the leaves. Otherwise the larger node is split,
+    /// and pairs of leaves are summed exactly. For the same accuracy this needs a larger `theta`
+    /// than the particle-cell walk, which should be less than 1.
+    ///
+    /// The tree must be built with [`OctreeOptions::leaf_only`], so that only leaves hold
+    /// points, and without periodic boundaries.
+    ///
+    /// [`OctreeOptions::leaf_only`]: crate::octtree::OctreeOptions::leaf_only
+    pub fn fmm_fields(&self, theta: S, softening: S) -> Vec<(S::Vector, S)> {
+        assert!(
+            self.options().leaf_only || self.children().is_none(),
+            "The fast multipole method needs a leaf only tree"
+        );
+        assert!(
+            self.options().periodic.is_none(),
+            "The fast multipole method does not support periodic boundaries"
+        );
+
+        let mut fields = Vec::new();
+        descend(
+            self,
+            Local::default(),
+            vec![self],
+            theta * theta,
+            softening,
+            &mut fields,
+        );
+
+        scatter(fields, self.index_count())
+    }
+}
+
+/// Resolves the interactions of `target`, whose field from the nodes already accepted is
+/// `local`, with the nodes in `sources`, appending the fields at the points below `target` to
+/// `fields`.
+fn descend<'a, S: Real>(
+    target: &'a Octree<S>,
+    mut local: Local<S>,
+    mut sources: Vec<&'a Octree<S>>,
+    theta_sq: S,
+    softening: S,
+    fields: &mut Vec<(usize, (S::Vector, S))>,
+) {
+    if target.children().is_none() && target.points().next().is_none() {
+        return;
+    }
+
+    let target_size = target.extent().component_max();
+    let (mut deferred, mut direct) = (Vec::new(), Vec::new());
+
+    while let Some(source) = sources.pop() {
+        if source.total_mass() == S::ZERO {
+            continue;
+        }
+
+        let source_size = source.extent().component_max();
+        let size = target_size + source_size;
+        let dist_sq = (source.com() - target.center()).mag_sq();
+
+        if size * size < theta_sq * dist_sq && !ptr::eq(target, source) {
+            local.add_cell(source, target.center(), softening);
+            continue;
+        }
+
+        match (target.children(), source.children()) {
+            (None, None) => direct.push(source),
+            (Some(_), None) => deferred.push(source),
+            (Some(_), Some(_)) if target_size >= source_size => deferred.push(source),
+            (_, Some(children)) => sources.extend(children.iter()),
+        }
+    }
+
+    let children = match target.children() {
+        Some(children) => children,
+        None => {
+            return target.points().for_each(|(idx, point, ..)| {
+                let near = local.shifted(point - target.center());
+                let field = direct.iter().fold((near.acc, near.pot), |field, source| {
+                    let (acc, pot) = source.bucket_field(point, softening);
+                    (field.0 + acc, field.1 + pot)
+                });
+                fields.push((idx, field));
+            });
+        }
+    };
+
+    let visit = |child: &'a Octree<S>, fields: &mut Vec<_>| {
+        let local = local.shifted(child.center() - target.center());
+        descend(child, local, deferred.clone(), theta_sq, softening, fields);
+    };
+
+    if target.depth() < PARALLEL_DEPTH {
+        let child_fields: Vec<Vec<_>> = children
+            .par_iter()
+            .map(|child| {
+                let mut fields = Vec::new();
+                visit(child, &mut fields);
+                fields
+            })
+            .collect();
+        fields.extend(child_fields.into_iter().flatten());
+    } else {
+        children.iter().for_each(|child| visit(child, fields));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        direct,
+        initial_conditions::unit_cube,
+        multipole::Multipole,
+        octtree::{Octree, OctreeOptions},
+        test_helpers::{check_after_remove_point, clustered, errors, masses, walk_fields},
+    };
+    use rand::prelude::*;
+
+    const SOFTENING: f32 = 1e-3;
+
+    #[test]
+    fn test_fmm_matches_direct_sum() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let points = clustered(2000, &mut rng);
+        let masses = masses(points.len(), &mut rng);
+        let exact = direct::fields(&points, &masses, SOFTENING);
+
+        for multipole in [Multipole::Monopole, Multipole::Quadrupole] {
+            let options = OctreeOptions {
+                leaf_only: true,
+                bucket_lanes: 2,
+                multipole,
+                ..Default::default()
+            };
+            let tree = Octree::construct_par_with(&points, &masses, options);
+
+            let fmm: Vec<_> = [0., 0.3, 0.5, 0.7]
+                .map(|theta| errors(&exact, &tree.fmm_fields(theta, SOFTENING)))
+                .to_vec();
+            let walk: Vec<_> = [0.3, 0.5, 0.7]
+                .map(|theta| errors(&exact, &walk_fields(&tree, &points, theta, SOFTENING)))
+                .to_vec();
+
+            assert!(
+                fmm[0].0 < 1e-5 && fmm[0].1 < 1e-5,
+                "{:?} {:?}",
+                multipole,
+                fmm
+            );
+            assert!(
+                fmm[1].0 < fmm[3].0 && fmm[1].1 < fmm[3].1,
+                "{:?} {:?}",
+                multipole,
+                fmm
+            );
+            assert!(
+                fmm[3].0 < 1e-2 && fmm[3].1 < 1e-3,
+                "{:?} {:?}",
+                multipole,
+                fmm
+            );
+
+            // Comparable accuracy to the monopole particle-cell walk at the same opening angle.
+            // The local expansion is second order, so quadrupoles gain the multipole method
+            // little.
+            if multipole == Multipole::Monopole {
+                assert!(fmm[2].0 < 3. * walk[1].0, "{:?} {:?}", fmm, walk);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fmm_f64() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let points = unit_cube::<f64>(1000, &mut rng);
+        let masses = vec![1f64; points.len()];
+
+        let options = OctreeOptions {
+            leaf_only: true,
+            ..Default::default()
+        };
+        let tree = Octree::construct_with(&points, &masses, options);
+        let exact = direct::fields(&points, &masses, 1e-3);
+        let (acc, pot) = errors(&exact, &tree.fmm_fields(0.5, 1e-3));
+        assert!(acc < 5e-3 && pot < 1e-3, "{} {}", acc, pot);
+    }
+
+    #[test]
+    fn test_fmm_after_remove_point() {
+        let options = OctreeOptions {
+            leaf_only: true,
+            ..Default::default()
+        };
+        check_after_remove_point(options, |tree| tree.fmm_fields(0., SOFTENING));
+    }
+}
@@ -0,0 +1,153 @@
+use ultraviolet::Vec3;
+
+use crate::{
+    direct,
+    octtree::{Octree, OctreeOptions},
+    softening::Softening,
+};
+
+/// Source of gravitational accelerations and potentials for a set of particles.
+pub trait ForceProvider {
+    fn accelerations(&self, positions: &[Vec3], masses: &[f32]) -> Vec<Vec3>;
+
+    fn potentials(&self, positions: &[Vec3], masses: &[f32]) -> Vec<f32>;
+
+    /// Accelerations of only the particles in `targets`, from all particles.
+    fn accelerations_of(&self, positions: &[Vec3], masses: &[f32], targets: &[usize]) -> Vec<Vec3> {
+        let accelerations = self.accelerations(positions, masses);
+        targets.iter().map(|&i| accelerations[i]).collect()
+    }
+}
+
+/// Method used by [`TreeForces`] to evaluate the field of its tree at every particle. Fields of
+/// only some particles, e.g. for block timesteps, always use a particle-cell walk per particle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Solver {
+    /// A particle-cell walk of the tree for every particle, see [`Octree::fields`].
+    #[default]
+    BarnesHut,
+    /// The fast multipole method, see [`Octree::fmm_fields`]. The tree is always built leaf
+    /// only.
+    Fmm,
+    /// One interaction list shared by the particles of each node, see [`Octree::group_fields`].
+    Group,
+}
+
+impl Solver {
+    /// Look up a solver by name, e.g. from the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "barnes-hut" | "bh" => Some(Solver::BarnesHut),
+            "fmm" => Some(Solver::Fmm),
+            "group" => Some(Solver::Group),
+            _ => None,
+        }
+    }
+}
+
+/// Tree code forces from an [`Octree`] built on each call.
+#[derive(Clone, Copy, Debug)]
+pub struct TreeForces {
+    pub theta: f32,
+    pub softening: f32,
+    pub options: OctreeOptions,
+    pub solver: Solver,
+}
+
+impl TreeForces {
+    pub fn new(theta: f32, softening: f32) -> Self {
+        Self {
+            theta,
+            softening,
+            options: OctreeOptions::default(),
+            solver: Solver::default(),
+        }
+    }
+
+    fn tree(&self, positions: &[Vec3], masses: &[f32]) -> Octree {
+        let options = OctreeOptions {
+            leaf_only: self.options.leaf_only || self.solver == Solver::Fmm,
+            ..self.options
+        };
+        Octree::construct_par_with(positions, masses, options)
+    }
+
+    /// Accelerations and potentials at every particle.
+    fn fields(&self, positions: &[Vec3], masses: &[f32]) -> Vec<(Vec3, f32)> {
+        let tree = self.tree(positions, masses);
+        match self.solver {
+            Solver::BarnesHut => tree.fields(positions, self.theta, self.softening),
+            Solver::Fmm => tree.fmm_fields(self.theta, self.softening),
+            Solver::Group => tree.group_fields(self.theta, self.softening),
+        }
+    }
+}
+
+impl ForceProvider for TreeForces {
+    fn accelerations(&self, positions: &[Vec3], masses: &[f32]) -> Vec<Vec3> {
+        self.fields(positions, masses)
+            .into_iter()
+            .map(|(acc, _)| acc)
+            .collect()
+    }
+
+    fn potentials(&self, positions: &[Vec3], masses: &[f32]) -> Vec<f32> {
+        self.fields(positions, masses)
+            .into_iter()
+            .map(|(_, pot)| pot)
+            .collect()
+    }
+
+    /// Walks the tree for each target alone with every solver, since [`Solver::Fmm`] and
+    /// [`Solver::Group`] only evaluate all particles at once.
+    fn accelerations_of(&self, positions: &[Vec3], masses: &[f32], targets: &[usize]) -> Vec<Vec3> {
+        let targets: Vec<Vec3> = targets.iter().map(|&i| positions[i]).collect();
+        self.tree(positions, masses)
+            .accelerations(&targets, self.theta, self.softening)
+    }
+}
+
+/// Exact forces from [`direct`] summation.
+#[derive(Clone, Copy, Debug)]
+pub struct DirectForces {
+    pub softening: f32,
+    pub kernel: Softening,
+}
+
+impl DirectForces {
+    pub fn new(softening: f32) -> Self {
+        Self {
+            softening,
+            kernel: Softening::default(),
+        }
+    }
+
+    fn fields(&self, positions: &[Vec3], masses: &[f32]) -> Vec<(Vec3, f32)> {
+        let softenings = vec![self.softening; positions.len()];
+        direct::fields_with(positions, masses, &softenings, self.kernel)
+    }
+}
+
+impl ForceProvider for DirectForces {
+    fn accelerations(&self, positions: &[Vec3], masses: &[f32]) -> Vec<Vec3> {
+        self.fields(positions, masses)
+            .into_iter()
+            .map(|(acc, _)| acc)
+            .collect()
+    }
+
+    fn potentials(&self, positions: &[Vec3], masses: &[f32]) -> Vec<f32> {
+        self.fields(positions, masses)
+            .into_iter()
+            .map(|(_, pot)| pot)
+            .collect()
+    }
+
+    fn accelerations_of(&self, positions: &[Vec3], masses: &[f32], targets: &[usize]) -> Vec<Vec3> {
+        let softenings = vec![self.softening; positions.len()];
+        direct::fields_of(positions, masses, &softenings, self.kernel, targets)
+            .into_iter()
+            .map(|(acc, _)| acc)
+            .collect()
+    }
+}
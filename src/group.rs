@@ -0,0 +1,356 @@
+use rayon::prelude::*;
+
+use crate::{
+    direct::{lane_field, pack},
+    kernel::Gravity,
+    multipole::Multipole,
+    octtree::{scatter, Octree},
+    real::{Real, Vector},
+};
+
+/// Lengths of the interaction lists built by [`Octree::group_fields`], summed over all groups.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InteractionStats {
+    /// Number of groups, the nodes whose points share an interaction list.
+    pub groups: usize,
+    /// Number of points in all groups.
+    pub targets: usize,
+    /// Cells accepted as a whole.
+    pub cells: usize,
+    /// Points summed directly.
+    pub particles: usize,
+    /// Longest list of accepted cells of any group.
+    pub max_cells: usize,
+    /// Longest list of directly summed points of any group.
+    pub max_particles: usize,
+    /// Interactions evaluated, each group's points times the length of its list.
+    pub interactions: usize,
+}
+
+impl InteractionStats {
+    /// Mean number of accepted cells per group.
+    pub fn mean_cells(&self) -> f64 {
+        self.cells as f64 / self.groups.max(1) as f64
+    }
+
+    /// Mean number of directly summed points per group.
+    pub fn mean_particles(&self) -> f64 {
+        self.particles as f64 / self.groups.max(1) as f64
+    }
+
+    /// Statistics of the lists of two disjoint sets of groups together.
+    fn merge(self, other: Self) -> Self {
+        Self {
+            groups: self.groups + other.groups,
+            targets: self.targets + other.targets,
+            cells: self.cells + other.cells,
+            particles: self.particles + other.particles,
+            max_cells: self.max_cells.max(other.max_cells),
+            max_particles: self.max_particles.max(other.max_particles),
+            interactions: self.interactions + other.interactions,
+        }
+    }
+}
+
+/// Interactions shared by all points of a group: nodes accepted as a whole, and nodes whose own
+/// points are summed directly.
+struct InteractionList<'a, S: Real> {
+    cells: Vec<&'a Octree<S>>,
+    buckets: Vec<&'a Octree<S>>,
+}
+
+impl<'a, S: Real> InteractionList<'a, S> {
+    /// Statistics of this list for a group of `targets` points.
+    fn stats(&self, targets: usize) -> InteractionStats {
+        let particles = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.points().count())
+            .sum();
+
+        InteractionStats {
+            groups: 1,
+            targets,
+            cells: self.cells.len(),
+            particles,
+            max_cells: self.cells.len(),
+            max_particles: particles,
+            interactions: targets * (self.cells.len() + particles),
+        }
+    }
+}
+
+impl<S: Real> Octree<S> {
+    /// Accelerations and potentials at every point in the tree, indexed as when the points were
+    /// inserted, with zeros at the indices of removed points, from one interaction list per group
+    /// instead of one tree walk per point. Every node holding points is a group, and shares a
+    /// list built by walking the tree once.
+    ///
+    /// A node is accepted for a whole group if `s / d < theta`, where `s` is the size of the node
+    /// and `d` the distance from its centre of mass to the nearest point of the group's box, so
+    /// every point of the group passes the opening criterion of [`Octree::field`]. The accepted
+    /// cells are packed into SIMD lanes by their monopoles, and are evaluated together with the
+    /// lanes of the directly summed points.
+    pub fn group_fields(&self, theta: S, softening: S) -> Vec<(S::Vector, S)> {
+        assert!(
+            self.options().periodic.is_none(),
+            "Group walks do not support periodic boundaries"
+        );
+
+        let fields: Vec<(usize, (S::Vector, S))> = self
+            .groups()
+            .par_iter()
+            .flat_map_iter(|group| {
+                let (indices, points): (Vec<usize>, Vec<S::Vector>) =
+                    group.points().map(|(idx, point, ..)| (idx, point)).unzip();
+                let list = self.group_list(group, theta * theta);
+                indices
+                    .into_iter()
+                    .zip(self.list_fields(&list, &points, softening))
+            })
+            .collect();
+
+        scatter(fields, self.index_count())
+    }
+
+    /// Accelerations and potentials at each of `points`, which should be close together, from
+    /// one interaction list shared by all of them. The list is built for the bounding box of the
+    /// points as for a group of [`Octree::group_fields`].
+    pub(crate) fn shared_fields(
+        &self,
+        points: &[S::Vector],
+        theta: S,
+        softening: S,
+    ) -> Vec<(S::Vector, S)> {
+        let Some(&first) = points.first() else {
+            return Vec::new();
+        };
+        let (min, max) = points.iter().fold((first, first), |(min, max), &point| {
+            (min.min_by_component(point), max.max_by_component(point))
+        });
+
+        let half = S::from_f64(0.5);
+        let list = self.interaction_list((min + max) * half, (max - min) * half, theta * theta);
+        self.list_fields(&list, points, softening)
+    }
+
+    /// Statistics of the interaction lists [`Octree::group_fields`] builds for `theta`.
+    pub fn interaction_stats(&self, theta: S) -> InteractionStats {
+        self.groups()
+            .par_iter()
+            .map(|group| {
+                let list = self.group_list(group, theta * theta);
+                list.stats(group.points().count())
+            })
+            .reduce(InteractionStats::default, InteractionStats::merge)
+    }
+
+    /// The nodes of this subtree which hold points.
+    fn groups(&self) -> Vec<&Octree<S>> {
+        let mut groups = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.points().next().is_some() {
+                groups.push(node);
+            }
+            stack.extend(node.children().iter().flat_map(|children| children.iter()));
+        }
+        groups
+    }
+
+    /// Interaction list of the points of `group`.
+    fn group_list<'a>(&'a self, group: &Octree<S>, theta_sq: S) -> InteractionList<'a, S> {
+        let half_extent = group.extent() / S::from_f64(2.0);
+        self.interaction_list(group.center(), half_extent, theta_sq)
+    }
+
+    /// Walks this subtree, collecting the interactions of all points in the box at `center` with
+    /// half its size `half_extent`.
+    fn interaction_list(
+        &self,
+        center: S::Vector,
+        half_extent: S::Vector,
+        theta_sq: S,
+    ) -> InteractionList<'_, S> {
+        let mut list = InteractionList {
+            cells: Vec::new(),
+            buckets: Vec::new(),
+        };
+
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.positive().0 == S::ZERO && node.negative().0 == S::ZERO {
+                continue;
+            }
+
+            let children = match node.children() {
+                Some(children) => children,
+                None => {
+                    list.buckets.push(node);
+                    continue;
+                }
+            };
+
+            let disp = (node.com() - center).abs();
+            let gap = S::Vector::new(
+                (disp[0] - half_extent[0]).max(S::ZERO),
+                (disp[1] - half_extent[1]).max(S::ZERO),
+                (disp[2] - half_extent[2]).max(S::ZERO),
+            );
+            let size = node.extent().component_max();
+            if size * size < theta_sq * gap.mag_sq() {
+                list.cells.push(node);
+                continue;
+            }
+
+            if node.points().next().is_some() {
+                list.buckets.push(node);
+            }
+            stack.extend(children.iter());
+        }
+
+        list
+    }
+
+    /// Fields at each of `points` from `list`.
+    fn list_fields(
+        &self,
+        list: &InteractionList<S>,
+        points: &[S::Vector],
+        softening: S,
+    ) -> Vec<(S::Vector, S)> {
+        let (kernel, multipole) = (Gravity(self.options().softening), self.options().multipole);
+
+        // Quadrupoles are evaluated cell by cell instead.
+        let cells = match multipole {
+            Multipole::Monopole => {
+                let coms: Vec<S::Vector> = list.cells.iter().map(|cell| cell.com()).collect();
+                let masses: Vec<S> = list.cells.iter().map(|cell| cell.total_mass()).collect();
+                let softenings: Vec<S> = list
+                    .cells
+                    .iter()
+                    .map(|cell| cell.softening_length())
+                    .collect();
+                pack(&coms, &masses, &softenings)
+            }
+            Multipole::Quadrupole => Vec::new(),
+        };
+
+        points
+            .iter()
+            .map(|&point| {
+                let mut field = (S::Vector::zero(), S::ZERO);
+                let mut add = |(acc, pot): (S::Vector, S)| {
+                    field.0 += acc;
+                    field.1 += pot;
+                };
+
+                match multipole {
+                    Multipole::Monopole => cells.iter().for_each(|&(coms, masses, softenings)| {
+                        add(lane_field(
+                            coms, masses, softenings, point, softening, &kernel,
+                        ))
+                    }),
+                    Multipole::Quadrupole => list
+                        .cells
+                        .iter()
+                        .for_each(|cell| add(cell.cell_field(point, softening))),
+                }
+                list.buckets
+                    .iter()
+                    .for_each(|bucket| add(bucket.bucket_field(point, softening)));
+
+                field
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        direct,
+        initial_conditions::unit_cube,
+        multipole::Multipole,
+        octtree::{Octree, OctreeOptions},
+        test_helpers::{check_after_remove_point, errors, masses, uniform, walk_fields},
+    };
+    use rand::prelude::*;
+
+    const SOFTENING: f32 = 1e-3;
+
+    #[test]
+    fn test_group_walk_matches_direct_sum() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let points = uniform(2000, &mut rng);
+        let masses = masses(points.len(), &mut rng);
+        let exact = direct::fields(&points, &masses, SOFTENING);
+
+        for (leaf_only, multipole) in [
+            (false, Multipole::Monopole),
+            (true, Multipole::Monopole),
+            (true, Multipole::Quadrupole),
+        ] {
+            let options = OctreeOptions {
+                leaf_only,
+                bucket_lanes: 2,
+                multipole,
+                ..Default::default()
+            };
+            let tree = Octree::construct_with(&points, &masses, options);
+
+            let opened = tree.group_fields(0., SOFTENING);
+            assert!(errors(&exact, &opened).0 < 1e-5);
+
+            // Every point passes the per-point opening criterion for the cells of its group, so
+            // the shared lists are at least as accurate as the particle-cell walk.
+            let group = errors(&exact, &tree.group_fields(0.5, SOFTENING)).0;
+            let walk = errors(&exact, &walk_fields(&tree, &points, 0.5, SOFTENING)).0;
+            assert!(group <= walk && group < 5e-3, "{} {}", group, walk);
+        }
+    }
+
+    #[test]
+    fn test_interaction_stats() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let points = unit_cube::<f32>(1000, &mut rng);
+        let masses = vec![1f32; points.len()];
+        let options = OctreeOptions {
+            leaf_only: true,
+            ..Default::default()
+        };
+        let tree = Octree::construct_with(&points, &masses, options);
+
+        // Opening every cell sums every pair directly.
+        let opened = tree.interaction_stats(0.);
+        assert_eq!(opened.targets, points.len());
+        assert_eq!((opened.cells, opened.max_cells), (0, 0));
+        assert_eq!(opened.particles, opened.groups * points.len());
+        assert_eq!(opened.max_particles, points.len());
+        assert_eq!(opened.interactions, points.len() * points.len());
+
+        let stats = tree.interaction_stats(0.7);
+        assert_eq!(
+            (stats.groups, stats.targets),
+            (opened.groups, opened.targets)
+        );
+        assert!(
+            stats.cells > 0 && stats.max_cells <= stats.cells,
+            "{:?}",
+            stats
+        );
+        assert!(
+            stats.mean_particles() < 0.5 * points.len() as f64,
+            "{:?}",
+            stats
+        );
+        assert!(stats.interactions < opened.interactions / 2, "{:?}", stats);
+    }
+
+    #[test]
+    fn test_group_walk_after_remove_point() {
+        check_after_remove_point(OctreeOptions::default(), |tree| {
+            tree.group_fields(0., SOFTENING)
+        });
+    }
+}
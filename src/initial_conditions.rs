@@ -0,0 +1,70 @@
+use rand::Rng;
+use ultraviolet::Vec3;
+
+use crate::real::{Real, Vector};
+
+/// Samples an equilibrium Plummer sphere of `n` equal-mass particles with total mass 1 and
+/// scale radius 1 (Aarseth, Henon & Wielen 1974), shifted to zero centre of mass and momentum.
+/// Returns the positions, velocities and masses.
+pub fn plummer(n: usize, rng: &mut impl Rng) -> (Vec<Vec3>, Vec<Vec3>, Vec<f32>) {
+    let mut positions = Vec::with_capacity(n);
+    let mut velocities = Vec::with_capacity(n);
+
+    (0..n).for_each(|_| {
+        // Truncating the cumulative mass avoids the few particles at huge radii.
+        let m: f32 = rng.gen_range(1e-3..0.999);
+        let r = 1. / (m.powf(-2. / 3.) - 1.).sqrt();
+
+        // Von Neumann rejection for the speed as a fraction of the local escape speed.
+        let q = loop {
+            let (q, g): (f32, f32) = (rng.gen(), rng.gen::<f32>() * 0.1);
+            if g < q * q * (1. - q * q).powf(3.5) {
+                break q;
+            }
+        };
+        let escape_speed = 2f32.sqrt() * (1. + r * r).powf(-0.25);
+
+        positions.push(random_direction(rng) * r);
+        velocities.push(random_direction(rng) * (q * escape_speed));
+    });
+
+    let com = positions.iter().fold(Vec3::zero(), |a, &b| a + b) / n as f32;
+    let mean_velocity = velocities.iter().fold(Vec3::zero(), |a, &b| a + b) / n as f32;
+    positions.iter_mut().for_each(|x| *x -= com);
+    velocities.iter_mut().for_each(|v| *v -= mean_velocity);
+
+    (positions, velocities, vec![1. / n as f32; n])
+}
+
+/// Samples `n` points uniformly distributed in the unit cube `[0, 1)^3`, of precision `S`.
+pub fn unit_cube<S: Real>(n: usize, rng: &mut impl Rng) -> Vec<S::Vector> {
+    let mut sample = || S::from_f64(rng.gen());
+    (0..n)
+        .map(|_| S::Vector::new(sample(), sample(), sample()))
+        .collect()
+}
+
+/// Uniformly distributed unit vector.
+pub fn random_direction(rng: &mut impl Rng) -> Vec3 {
+    let z: f32 = rng.gen_range(-1.0..1.0);
+    let phi: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+    let s = (1. - z * z).sqrt();
+    Vec3::new(s * phi.cos(), s * phi.sin(), z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::plummer;
+    use crate::{forces::DirectForces, simulation::Particles};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_plummer_virial_equilibrium() {
+        let forces = DirectForces::new(0.);
+        let (positions, velocities, masses) = plummer(2000, &mut StdRng::seed_from_u64(0));
+        let particles = Particles::new(positions, velocities, masses, &forces);
+
+        let virial_ratio = 2. * particles.kinetic_energy() / -particles.potential_energy(&forces);
+        assert!((virial_ratio - 1.).abs() < 0.1, "{}", virial_ratio);
+    }
+}
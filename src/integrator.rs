@@ -0,0 +1,177 @@
+use std::fmt::Debug;
+
+use ultraviolet::Vec3;
+
+use crate::{forces::ForceProvider, simulation::Particles};
+
+/// Time integration scheme advancing [`Particles`] by one step.
+///
+/// On entry `particles.accelerations` holds the accelerations at the current positions, and
+/// implementations must leave it consistent with the new positions.
+pub trait Integrator: Debug + Send + Sync {
+    fn step(&self, particles: &mut Particles, forces: &dyn ForceProvider, dt: f32);
+}
+
+/// Looks up an integrator by name, e.g. to select one from the command line.
+pub fn from_name(name: &str) -> Option<Box<dyn Integrator>> {
+    match name {
+        "euler" => Some(Box::new(SymplecticEuler)),
+        "verlet" | "leapfrog" => Some(Box::new(VelocityVerlet)),
+        "rk4" => Some(Box::new(RungeKutta4)),
+        "yoshida" | "forest-ruth" => Some(Box::new(Yoshida4)),
+        _ => None,
+    }
+}
+
+/// First order symplectic Euler (kick then drift).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SymplecticEuler;
+
+impl Integrator for SymplecticEuler {
+    fn step(&self, particles: &mut Particles, forces: &dyn ForceProvider, dt: f32) {
+        particles.kick(dt);
+        particles.drift(dt);
+        particles.compute_accelerations(forces);
+    }
+}
+
+/// Second order velocity Verlet, i.e. the kick-drift-kick leapfrog.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VelocityVerlet;
+
+impl Integrator for VelocityVerlet {
+    fn step(&self, particles: &mut Particles, forces: &dyn ForceProvider, dt: f32) {
+        particles.kick(dt / 2.);
+        particles.drift(dt);
+        particles.compute_accelerations(forces);
+        particles.kick(dt / 2.);
+    }
+}
+
+/// Classical fourth order Runge-Kutta. Not symplectic, so energy drifts secularly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RungeKutta4;
+
+impl Integrator for RungeKutta4 {
+    fn step(&self, particles: &mut Particles, forces: &dyn ForceProvider, dt: f32) {
+        let x0 = particles.positions.clone();
+        let v0 = particles.velocities.clone();
+        let masses = &particles.masses;
+
+        let offset = |base: &[Vec3], delta: &[Vec3], h: f32| -> Vec<Vec3> {
+            base.iter().zip(delta).map(|(&b, &d)| b + d * h).collect()
+        };
+
+        let (k1x, k1v) = (v0.clone(), particles.accelerations.clone());
+
+        let k2x = offset(&v0, &k1v, dt / 2.);
+        let k2v = forces.accelerations(&offset(&x0, &k1x, dt / 2.), masses);
+
+        let k3x = offset(&v0, &k2v, dt / 2.);
+        let k3v = forces.accelerations(&offset(&x0, &k2x, dt / 2.), masses);
+
+        let k4x = offset(&v0, &k3v, dt);
+        let k4v = forces.accelerations(&offset(&x0, &k3x, dt), masses);
+
+        let combine = |k1: &[Vec3], k2: &[Vec3], k3: &[Vec3], k4: &[Vec3], out: &mut [Vec3]| {
+            out.iter_mut().enumerate().for_each(|(i, o)| {
+                *o += (k1[i] + 2. * k2[i] + 2. * k3[i] + k4[i]) * (dt / 6.);
+            })
+        };
+
+        combine(&k1x, &k2x, &k3x, &k4x, &mut particles.positions);
+        combine(&k1v, &k2v, &k3v, &k4v, &mut particles.velocities);
+        particles.compute_accelerations(forces);
+    }
+}
+
+/// Fourth order symplectic scheme of Forest-Ruth and Yoshida, composing three leapfrog steps
+/// with weights `w1, w0, w1`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Yoshida4;
+
+impl Integrator for Yoshida4 {
+    fn step(&self, particles: &mut Particles, forces: &dyn ForceProvider, dt: f32) {
+        let cbrt_2 = 2f32.cbrt();
+        let w1 = 1. / (2. - cbrt_2);
+        let w0 = -cbrt_2 * w1;
+
+        let kicks = [w1 / 2., (w0 + w1) / 2., (w0 + w1) / 2., w1 / 2.];
+        let drifts = [w1, w0, w1];
+
+        particles.kick(kicks[0] * dt);
+        drifts.iter().zip(&kicks[1..]).for_each(|(&d, &k)| {
+            particles.drift(d * dt);
+            particles.compute_accelerations(forces);
+            particles.kick(k * dt);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Integrator, RungeKutta4, SymplecticEuler, VelocityVerlet, Yoshida4};
+    use crate::{forces::DirectForces, simulation::Particles};
+    use std::f32::consts::PI;
+    use ultraviolet::Vec3;
+
+    /// Largest relative energy error during each orbit of an `e = 0.5` Kepler orbit with period
+    /// `2 pi`.
+    fn kepler_energy_errors(integrator: &dyn Integrator, orbits: usize) -> Vec<f32> {
+        let forces = DirectForces::new(0.);
+        let eccentricity = 0.5f32;
+
+        // Unit semi-major axis and total mass, starting at pericentre.
+        let r = 1. - eccentricity;
+        let v = ((1. + eccentricity) / r).sqrt();
+        let mut particles = Particles::new(
+            vec![Vec3::new(-r / 2., 0., 0.), Vec3::new(r / 2., 0., 0.)],
+            vec![Vec3::new(0., -v / 2., 0.), Vec3::new(0., v / 2., 0.)],
+            vec![0.5, 0.5],
+            &forces,
+        );
+        let initial_energy = particles.total_energy(&forces);
+
+        let steps_per_orbit = 64;
+        let dt = 2. * PI / steps_per_orbit as f32;
+
+        (0..orbits)
+            .map(|_| {
+                (0..steps_per_orbit)
+                    .map(|_| {
+                        integrator.step(&mut particles, &forces, dt);
+                        ((particles.total_energy(&forces) - initial_energy) / initial_energy).abs()
+                    })
+                    .fold(0., f32::max)
+            })
+            .collect()
+    }
+
+    fn max(errors: &[f32]) -> f32 {
+        errors.iter().copied().fold(0., f32::max)
+    }
+
+    #[test]
+    fn test_kepler_energy() {
+        let orbits = 20;
+
+        let euler = kepler_energy_errors(&SymplecticEuler, orbits);
+        let verlet = kepler_energy_errors(&VelocityVerlet, orbits);
+        let rk4 = kepler_energy_errors(&RungeKutta4, orbits);
+        let yoshida = kepler_energy_errors(&Yoshida4, orbits);
+
+        // Symplectic schemes have bounded energy error without secular drift.
+        for errors in [&euler, &verlet, &yoshida] {
+            let (first, second) = errors.split_at(orbits / 2);
+            assert!(max(second) < 1.5 * max(first), "{:?}", errors);
+        }
+
+        assert!(max(&euler) < 0.5, "{:?}", euler);
+        assert!(max(&verlet) < 5e-2, "{:?}", verlet);
+        assert!(max(&yoshida) < max(&verlet) / 10., "{:?}", yoshida);
+
+        // RK4 is accurate per orbit, but its energy error accumulates over the run.
+        assert!(rk4[0] < max(&yoshida), "{:?}", rk4);
+        assert!(rk4[orbits - 1] > 10. * rk4[0], "{:?}", rk4);
+    }
+}
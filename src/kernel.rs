@@ -0,0 +1,282 @@
+use crate::{
+    direct::lane_field,
+    octtree::{map_morton_par, Interaction, Octree},
+    real::{Real, Vector, Wide},
+    softening::Softening,
+};
+
+/// Pairwise interaction evaluated by the tree walk, generalising gravity to any radial
+/// potential. The masses a tree is built with are the strengths of the sources, e.g. signed
+/// charges.
+///
+/// A source of strength `q` at displacement `d` from the target, with `r = |d|`, contributes the
+/// potential `q phi(r)` and the acceleration `q f(r) d`, where `f(r) = phi'(r) / r` for a force
+/// derived from the potential. Both are evaluated with a softening length `h`, which kernels
+/// without softening may ignore.
+pub trait Kernel<S: Real>: Send + Sync {
+    /// The factors `(phi, f)` at squared separation `r_sq`, with softening length `h`.
+    fn factors(&self, r_sq: S, h: S) -> (S, S);
+
+    /// [`Kernel::factors`] for each lane. Evaluates the lanes one at a time unless overridden.
+    fn lane_factors(&self, r_sq: S::Wide, h: S::Wide) -> (S::Wide, S::Wide) {
+        let (r_sq, h) = (r_sq.to_array(), h.to_array());
+        let (mut phi, mut f) = (S::Array::default(), S::Array::default());
+
+        (0..S::LANES).for_each(|i| {
+            (phi.as_mut()[i], f.as_mut()[i]) = self.factors(r_sq.as_ref()[i], h.as_ref()[i]);
+        });
+
+        (S::Wide::new(phi), S::Wide::new(f))
+    }
+}
+
+/// Attractive gravity (with `G = 1`), `phi = -1 / r`, softened by the given kernel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Gravity(pub Softening);
+
+impl<S: Real> Kernel<S> for Gravity {
+    fn factors(&self, r_sq: S, h: S) -> (S, S) {
+        let (inv_r, inv_r3) = self.0.factors(r_sq, h);
+        (-inv_r, inv_r3)
+    }
+
+    fn lane_factors(&self, r_sq: S::Wide, h: S::Wide) -> (S::Wide, S::Wide) {
+        let (inv_r, inv_r3) = self.0.lane_factors::<S>(r_sq, h);
+        (-inv_r, inv_r3)
+    }
+}
+
+/// Electrostatics (with Coulomb's constant 1), `phi = 1 / r`, softened by the given kernel. Like
+/// charges repel, and the acceleration is that of a unit positive charge of unit mass.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Coulomb(pub Softening);
+
+impl<S: Real> Kernel<S> for Coulomb {
+    fn factors(&self, r_sq: S, h: S) -> (S, S) {
+        let (inv_r, inv_r3) = self.0.factors(r_sq, h);
+        (inv_r, -inv_r3)
+    }
+
+    fn lane_factors(&self, r_sq: S::Wide, h: S::Wide) -> (S::Wide, S::Wide) {
+        let (inv_r, inv_r3) = self.0.lane_factors::<S>(r_sq, h);
+        (inv_r, -inv_r3)
+    }
+}
+
+/// Screened Coulomb potential `phi = exp(-r / screening) / r`, with Plummer softening: `r` is
+/// replaced by `sqrt(r^2 + h^2)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Yukawa<S: Real> {
+    pub screening: S,
+}
+
+impl<S: Real> Kernel<S> for Yukawa<S> {
+    fn factors(&self, r_sq: S, h: S) -> (S, S) {
+        let r = (r_sq + h * h).sqrt();
+        let phi = (-r / self.screening).exp() / r;
+        (phi, -phi * (S::ONE / r + S::ONE / self.screening) / r)
+    }
+}
+
+/// User defined kernel from a function of `(r_sq, h)` returning `(phi, f)`, see [`Kernel`].
+#[derive(Clone, Copy, Debug)]
+pub struct Radial<F>(pub F);
+
+impl<S: Real, F: Fn(S, S) -> (S, S) + Send + Sync> Kernel<S> for Radial<F> {
+    fn factors(&self, r_sq: S, h: S) -> (S, S) {
+        (self.0)(r_sq, h)
+    }
+}
+
+impl<S: Real> Octree<S> {
+    /// Acceleration and potential at `point` under `kernel`, with the masses of the tree as the
+    /// source strengths, from the same walk and opening criterion as [`Octree::field`].
+    ///
+    /// Accepted cells are approximated by two monopoles, the positive and the negative strengths
+    /// at their own centres, so cells which are nearly neutral overall, like a plasma, keep their
+    /// dipole. The opening criterion measures the distance to the centre of the absolute
+    /// strengths. Quadrupoles are not used, and the tree must not be periodic.
+    pub fn kernel_field<K: Kernel<S>>(
+        &self,
+        point: S::Vector,
+        theta: S,
+        softening: S,
+        kernel: &K,
+    ) -> (S::Vector, S) {
+        assert!(
+            self.options().periodic.is_none(),
+            "Kernels other than gravity do not support periodic boundaries"
+        );
+        let mut field = (S::Vector::zero(), S::ZERO);
+
+        self.walk(point, theta * theta, &mut |interaction| {
+            let (acc, pot) = match interaction {
+                Interaction::Cell(node) => node.kernel_cell_field(point, softening, kernel),
+                Interaction::Bucket(node) => node.lanes().fold(
+                    (S::Vector::zero(), S::ZERO),
+                    |acc, &(_, positions, masses, softenings)| {
+                        let (a, p) =
+                            lane_field(positions, masses, softenings, point, softening, kernel);
+                        (acc.0 + a, acc.1 + p)
+                    },
+                ),
+            };
+            field.0 += acc;
+            field.1 += pot;
+        });
+
+        field
+    }
+
+    /// Accelerations and potentials at each of `points` under `kernel`, see
+    /// [`Octree::kernel_field`] and [`Octree::fields`].
+    pub fn kernel_fields<K: Kernel<S>>(
+        &self,
+        points: &[S::Vector],
+        theta: S,
+        softening: S,
+        kernel: &K,
+    ) -> Vec<(S::Vector, S)> {
+        map_morton_par::<S, _>(points, self.center(), self.extent(), |point| {
+            self.kernel_field(point, theta, softening, kernel)
+        })
+    }
+
+    /// Field at `point` from the positive and negative strengths of this node as two monopoles.
+    fn kernel_cell_field<K: Kernel<S>>(
+        &self,
+        point: S::Vector,
+        softening: S,
+        kernel: &K,
+    ) -> (S::Vector, S) {
+        let h = self.softening_length().max(softening);
+
+        [self.positive(), self.negative()]
+            .into_iter()
+            .filter(|&(strength, _)| strength != S::ZERO)
+            .fold(
+                (S::Vector::zero(), S::ZERO),
+                |(acc, pot), (strength, center)| {
+                    let d = center - point;
+                    let (phi, f) = kernel.factors(d.mag_sq(), h);
+                    (acc + d * (strength * f), pot + strength * phi)
+                },
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Coulomb, Gravity, Kernel, Radial, Yukawa};
+    use crate::{
+        direct,
+        initial_conditions::unit_cube,
+        octtree::{Octree, OctreeOptions},
+        softening::Softening,
+        test_helpers::{errors, walk_fields},
+    };
+    use rand::prelude::*;
+    use ultraviolet::{f64x4, DVec3};
+
+    const SOFTENING: f64 = 1e-3;
+
+    #[test]
+    fn test_kernel_forces_derive_from_potentials() {
+        let check = |kernel: &dyn Kernel<f64>| {
+            for r in [0.05, 0.3, 1., 2.5] {
+                let f = kernel.factors(r * r, 0.1).1;
+                let above = kernel.factors((r + 1e-6) * (r + 1e-6), 0.1).0;
+                let below = kernel.factors((r - 1e-6) * (r - 1e-6), 0.1).0;
+                let derivative = (above - below) / 2e-6;
+                assert!((derivative / r - f).abs() < 1e-6 * f.abs(), "{}", r);
+            }
+        };
+
+        check(&Gravity(Softening::Plummer));
+        check(&Gravity(Softening::Spline));
+        check(&Coulomb(Softening::Plummer));
+        check(&Yukawa { screening: 0.5 });
+        check(&Radial(|r_sq: f64, _| (r_sq * r_sq, 4. * r_sq)));
+
+        // The SIMD factors agree with the scalar ones.
+        let r_sq = [0.01, 0.1, 1., 4.];
+        let (phi, f) = Kernel::<f64>::lane_factors(
+            &Yukawa { screening: 0.5 },
+            f64x4::new(r_sq),
+            f64x4::splat(0.1),
+        );
+        r_sq.iter().enumerate().for_each(|(i, &r_sq)| {
+            let (p, q) = Yukawa { screening: 0.5 }.factors(r_sq, 0.1);
+            assert_eq!((phi.to_array()[i], f.to_array()[i]), (p, q));
+        });
+    }
+
+    #[test]
+    fn test_gravity_kernel_matches_field() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let points = unit_cube::<f64>(1000, &mut rng);
+        let masses: Vec<f64> = (0..points.len()).map(|_| rng.gen_range(0.5..1.5)).collect();
+        let tree = Octree::construct(&points, &masses);
+
+        let kernel = tree.kernel_fields(&points, 0.5, SOFTENING, &Gravity::default());
+        let field = walk_fields(&tree, &points, 0.5, SOFTENING);
+        kernel.iter().zip(&field).for_each(|(k, f)| {
+            assert!((k.0 - f.0).mag() < 1e-9 * f.0.mag() && (k.1 - f.1).abs() < 1e-9 * f.1.abs());
+        });
+    }
+
+    #[test]
+    fn test_neutral_plasma() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let points = unit_cube::<f64>(1000, &mut rng);
+        // Pairs of opposite charges a short distance apart, so the plasma is neutral overall
+        // and every cell holds nearly canceling charges.
+        let points: Vec<DVec3> = points
+            .iter()
+            .flat_map(|&p| [p, p + DVec3::new(rng.gen(), rng.gen(), rng.gen()) * 0.01])
+            .collect();
+        let charges: Vec<f64> = (0..points.len())
+            .map(|i| if i % 2 == 0 { 1. } else { -1. })
+            .collect();
+
+        let options = OctreeOptions {
+            padding: 0.01,
+            ..Default::default()
+        };
+        let tree = Octree::construct_with(&points, &charges, options);
+        tree.validate().unwrap();
+        assert!(tree.total_mass().abs() < 1e-9);
+        assert!((tree.positive().0 - 1000.).abs() < 1e-9);
+
+        let kernel = Coulomb(Softening::Plummer);
+        let softenings = vec![SOFTENING; points.len()];
+        let exact = direct::fields_with_kernel(&points, &charges, &softenings, &kernel);
+
+        let opened = errors(&exact, &tree.kernel_fields(&points, 0., SOFTENING, &kernel));
+        assert!(opened.0 < 1e-9 && opened.1 < 1e-9, "{:?}", opened);
+
+        // Separate monopoles for the two signs keep the dipoles of the pairs.
+        let approx = errors(
+            &exact,
+            &tree.kernel_fields(&points, 0.5, SOFTENING, &kernel),
+        );
+        assert!(approx.0 < 1e-2 && approx.1 < 1e-2, "{:?}", approx);
+    }
+
+    #[test]
+    fn test_yukawa_matches_direct_sum() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let points = unit_cube::<f64>(2000, &mut rng);
+        let charges: Vec<f64> = (0..points.len()).map(|_| rng.gen_range(0.5..1.5)).collect();
+        let tree = Octree::construct(&points, &charges);
+
+        let kernel = Yukawa { screening: 0.2 };
+        let softenings = vec![SOFTENING; points.len()];
+        let exact = direct::fields_with_kernel(&points, &charges, &softenings, &kernel);
+        let approx = errors(
+            &exact,
+            &tree.kernel_fields(&points, 0.3, SOFTENING, &kernel),
+        );
+        assert!(approx.0 < 1e-2 && approx.1 < 1e-2, "{:?}", approx);
+    }
+}
@@ -1 +1,19 @@
+pub mod direct;
+pub mod ewald;
+pub mod fmm;
+pub mod forces;
+pub mod group;
+pub mod initial_conditions;
+pub mod integrator;
+pub mod kernel;
+pub mod linear;
+pub mod multipole;
 pub mod octtree;
+pub mod real;
+pub mod render;
+pub mod simulation;
+pub mod snapshot;
+pub mod softening;
+#[cfg(test)]
+mod test_helpers;
+pub mod timestep;
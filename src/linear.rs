@@ -0,0 +1,489 @@
+use std::ops::Range;
+
+use rayon::prelude::*;
+
+use crate::{
+    direct::{lane_field, pack},
+    kernel::Gravity,
+    multipole::{cell_field, Multipole, Quadrupole},
+    octtree::{bounds, map_morton_par, morton_key, OctreeOptions, MORTON_BITS},
+    real::{Real, Vector, Wide, WideVector},
+};
+
+/// Node of a [`LinearOctree`].
+#[derive(Clone, Debug)]
+struct Node<S: Real> {
+    center: S::Vector,
+    extent: S::Vector,
+    com: S::Vector,
+    total_mass: S,
+    quadrupole: Quadrupole<S>,
+    /// Largest softening length of the points in this node.
+    softening: S,
+    /// Range of the points in this node in Morton order.
+    points: Range<usize>,
+    /// Lanes holding the points of a leaf, empty for internal nodes.
+    lanes: Range<usize>,
+    /// Index of the first node after this node's subtree.
+    next: usize,
+}
+
+impl<S: Real> Node<S> {
+    fn is_leaf(&self) -> bool {
+        !self.lanes.is_empty()
+    }
+}
+
+/// Barnes-Hut octree stored as one contiguous `Vec` of nodes, an alternative layout to the
+/// boxed [`Octree`](crate::octtree::Octree).
+///
+/// Points are sorted on their 63-bit Morton keys within the root cell, so the points of every
+/// node are a contiguous range, and nodes are emitted in depth first order, so every subtree is
+/// a contiguous range of nodes which the force walk skips over without a stack. Only non-empty
+/// nodes are stored, and only leaves hold points, up to [`OctreeOptions::bucket_size`] each
+/// unless the leaf cannot be split. Depth is further limited to [`MORTON_BITS`].
+#[derive(Clone, Debug)]
+pub struct LinearOctree<S: Real = f32> {
+    nodes: Vec<Node<S>>,
+    /// Indices of the points in Morton order.
+    indices: Vec<usize>,
+    /// Positions, masses and softening lengths of the points in Morton order, packed per leaf.
+    lanes: Vec<(S::WideVector, S::Wide, S::Wide)>,
+    options: OctreeOptions<S>,
+}
+
+impl<S: Real> LinearOctree<S> {
+    pub fn construct(points: &[S::Vector], masses: &[S]) -> Self {
+        Self::construct_with(points, masses, OctreeOptions::default())
+    }
+
+    pub fn construct_with(points: &[S::Vector], masses: &[S], options: OctreeOptions<S>) -> Self {
+        assert_eq!(
+            points.len(),
+            masses.len(),
+            "Length of given points not equal to length of given masses"
+        );
+        assert!(
+            options.periodic.is_none(),
+            "Periodic boundaries are only supported by Octree"
+        );
+
+        let mut tree = Self {
+            nodes: Vec::new(),
+            indices: Vec::new(),
+            lanes: Vec::new(),
+            options,
+        };
+        if points.is_empty() {
+            return tree;
+        }
+
+        let (center, extent) = bounds(points, &options);
+
+        let mut keys: Vec<(u64, usize)> = points
+            .iter()
+            .enumerate()
+            .map(|(i, &point)| (morton_key::<S>(point, center, extent), i))
+            .collect();
+        keys.par_sort_unstable();
+
+        tree.indices = keys.iter().map(|&(_, i)| i).collect();
+        let keys: Vec<u64> = keys.iter().map(|&(key, _)| key).collect();
+        let points: Vec<S::Vector> = tree.indices.iter().map(|&i| points[i]).collect();
+        let masses: Vec<S> = tree.indices.iter().map(|&i| masses[i]).collect();
+
+        let sorted = Sorted {
+            keys: &keys,
+            points: &points,
+            masses: &masses,
+        };
+        tree.build(&sorted, 0..keys.len(), center, extent, 0);
+        tree
+    }
+
+    /// Appends the subtree of the points in `range` of `sorted`, returning the index of its root.
+    fn build(
+        &mut self,
+        sorted: &Sorted<S>,
+        range: Range<usize>,
+        center: S::Vector,
+        extent: S::Vector,
+        depth: u32,
+    ) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            center,
+            extent,
+            com: S::Vector::zero(),
+            total_mass: S::ZERO,
+            quadrupole: Quadrupole::default(),
+            softening: S::ZERO,
+            points: range.clone(),
+            lanes: 0..0,
+            next: 0,
+        });
+
+        let can_split = depth < self.options.max_depth.min(MORTON_BITS)
+            && extent.component_max() > self.options.min_size;
+
+        if range.len() <= self.options.bucket_size() || !can_split {
+            let first = self.lanes.len();
+            let zeros = vec![S::ZERO; range.len()];
+            self.lanes.extend(pack(
+                &sorted.points[range.clone()],
+                &sorted.masses[range],
+                &zeros,
+            ));
+            self.nodes[index].lanes = first..self.lanes.len();
+            self.compute_leaf(index);
+        } else {
+            // Child `i` lies on the positive side of axis `a` if bit `2 - a` of `i` is set.
+            let offset = |i: usize| {
+                let sign = |bit: usize| if i & bit != 0 { S::ONE } else { -S::ONE };
+                S::Vector::new(sign(4), sign(2), sign(1))
+            };
+            let shift = 3 * (MORTON_BITS - 1 - depth);
+
+            let mut children = Vec::with_capacity(8);
+            let mut start = range.start;
+            (0..8).for_each(|octant| {
+                let end = start
+                    + sorted.keys[start..range.end]
+                        .partition_point(|&key| (key >> shift) & 7 <= octant as u64);
+                if end > start {
+                    let child_center = center + extent * offset(octant) / S::from_f64(4.0);
+                    let child_extent = extent / S::from_f64(2.0);
+                    children.push(self.build(
+                        sorted,
+                        start..end,
+                        child_center,
+                        child_extent,
+                        depth + 1,
+                    ));
+                }
+                start = end;
+            });
+
+            self.compute_internal(index, &children);
+        }
+
+        self.nodes[index].next = self.nodes.len();
+        index
+    }
+
+    fn compute_leaf(&mut self, index: usize) {
+        let node = &self.nodes[index];
+        let lanes = &self.lanes[node.lanes.clone()];
+
+        let (com, total_mass) = lanes.iter().fold(
+            (S::Vector::zero(), S::ZERO),
+            |(com, total_mass), &(positions, masses, _)| {
+                (
+                    com + (positions * masses).reduce_add(),
+                    total_mass + masses.reduce_add(),
+                )
+            },
+        );
+        let com = if total_mass == S::ZERO {
+            S::Vector::zero()
+        } else {
+            com / total_mass
+        };
+
+        let quadrupole = match self.options.multipole {
+            Multipole::Monopole => Quadrupole::default(),
+            Multipole::Quadrupole => {
+                let com = S::WideVector::splat(com);
+                lanes
+                    .iter()
+                    .fold(Quadrupole::default(), |q, &(positions, masses, _)| {
+                        q + Quadrupole::lane(masses, positions - com)
+                    })
+            }
+        };
+
+        let node = &mut self.nodes[index];
+        (node.com, node.total_mass, node.quadrupole) = (com, total_mass, quadrupole);
+    }
+
+    fn compute_internal(&mut self, index: usize, children: &[usize]) {
+        let (com, total_mass) = children.iter().map(|&i| &self.nodes[i]).fold(
+            (S::Vector::zero(), S::ZERO),
+            |(com, total_mass), child| {
+                (
+                    com + child.com * child.total_mass,
+                    total_mass + child.total_mass,
+                )
+            },
+        );
+        let com = if total_mass == S::ZERO {
+            S::Vector::zero()
+        } else {
+            com / total_mass
+        };
+
+        let quadrupole =
+            match self.options.multipole {
+                Multipole::Monopole => Quadrupole::default(),
+                Multipole::Quadrupole => children.iter().map(|&i| &self.nodes[i]).fold(
+                    Quadrupole::default(),
+                    |q, child| {
+                        q + child.quadrupole + Quadrupole::point(child.total_mass, child.com - com)
+                    },
+                ),
+            };
+
+        let node = &mut self.nodes[index];
+        (node.com, node.total_mass, node.quadrupole) = (com, total_mass, quadrupole);
+    }
+
+    /// Sets the softening length of every point to its entry in `softenings`, indexed as the
+    /// points the tree was built from, see
+    /// [`Octree::set_softenings`](crate::octtree::Octree::set_softenings).
+    pub fn set_softenings(&mut self, softenings: &[S]) {
+        let sorted: Vec<S> = self.indices.iter().map(|&i| softenings[i]).collect();
+
+        let Self { nodes, lanes, .. } = self;
+        nodes.iter_mut().for_each(|node| {
+            node.softening = sorted[node.points.clone()]
+                .iter()
+                .fold(S::ZERO, |max, &softening| max.max(softening));
+
+            lanes[node.lanes.clone()]
+                .iter_mut()
+                .zip(sorted[node.points.clone()].chunks(S::LANES))
+                .for_each(|(lane, softenings)| {
+                    let mut array = S::Array::default();
+                    array.as_mut()[..softenings.len()].copy_from_slice(softenings);
+                    lane.2 = S::Wide::new(array);
+                });
+        });
+    }
+
+    /// Number of points in the tree.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Number of nodes in the tree.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Quadrupole moment about the centre of mass, zero unless enabled in the options.
+    pub fn quadrupole(&self) -> Quadrupole<S> {
+        self.nodes
+            .first()
+            .map_or_else(Quadrupole::default, |root| root.quadrupole)
+    }
+
+    /// Centre of mass of all points.
+    pub fn com(&self) -> S::Vector {
+        self.nodes
+            .first()
+            .map_or_else(S::Vector::zero, |root| root.com)
+    }
+
+    /// Total mass of all points.
+    pub fn total_mass(&self) -> S {
+        self.nodes.first().map_or(S::ZERO, |root| root.total_mass)
+    }
+
+    /// Gravitational acceleration at `point`, see
+    /// [`Octree::acceleration`](crate::octtree::Octree::acceleration).
+    pub fn acceleration(&self, point: S::Vector, theta: S, softening: S) -> S::Vector {
+        self.field(point, theta, softening).0
+    }
+
+    /// Gravitational potential at `point`, see [`LinearOctree::acceleration`].
+    pub fn potential(&self, point: S::Vector, theta: S, softening: S) -> S {
+        self.field(point, theta, softening).1
+    }
+
+    /// Acceleration and potential at `point` from a single tree walk.
+    pub fn field(&self, point: S::Vector, theta: S, softening: S) -> (S::Vector, S) {
+        let (theta_sq, kernel) = (theta * theta, Gravity(self.options.softening));
+        let mut field = (S::Vector::zero(), S::ZERO);
+
+        let mut index = 0;
+        while let Some(node) = self.nodes.get(index) {
+            let size = node.extent.component_max();
+
+            let (acc, pot) = if node.total_mass == S::ZERO {
+                (S::Vector::zero(), S::ZERO)
+            } else if node.is_leaf() {
+                self.lanes[node.lanes.clone()].iter().fold(
+                    (S::Vector::zero(), S::ZERO),
+                    |acc, &(positions, masses, softenings)| {
+                        let (a, p) =
+                            lane_field(positions, masses, softenings, point, softening, &kernel);
+                        (acc.0 + a, acc.1 + p)
+                    },
+                )
+            } else if size * size < theta_sq * (node.com - point).mag_sq() {
+                cell_field(
+                    self.options.multipole,
+                    node.total_mass,
+                    node.com,
+                    &node.quadrupole,
+                    point,
+                    node.softening.max(softening),
+                    kernel.0,
+                )
+            } else {
+                // Open the node by moving on to its first child.
+                index += 1;
+                continue;
+            };
+
+            field.0 += acc;
+            field.1 += pot;
+            index = node.next;
+        }
+
+        field
+    }
+
+    /// Accelerations at each of `points`, evaluated in parallel, see
+    /// [`Octree::accelerations`](crate::octtree::Octree::accelerations).
+    pub fn accelerations(&self, points: &[S::Vector], theta: S, softening: S) -> Vec<S::Vector> {
+        self.map_par(points, |point| self.acceleration(point, theta, softening))
+    }
+
+    /// Potentials at each of `points`, see [`LinearOctree::accelerations`].
+    pub fn potentials(&self, points: &[S::Vector], theta: S, softening: S) -> Vec<S> {
+        self.map_par(points, |point| self.potential(point, theta, softening))
+    }
+
+    /// Accelerations and potentials at each of `points`, see [`LinearOctree::accelerations`].
+    pub fn fields(&self, points: &[S::Vector], theta: S, softening: S) -> Vec<(S::Vector, S)> {
+        self.map_par(points, |point| self.field(point, theta, softening))
+    }
+
+    fn map_par<T: Copy + Default + Send>(
+        &self,
+        points: &[S::Vector],
+        f: impl Fn(S::Vector) -> T + Sync,
+    ) -> Vec<T> {
+        let (center, extent) = self.nodes.first().map_or_else(
+            || (S::Vector::zero(), S::Vector::broadcast(S::ONE)),
+            |root| (root.center, root.extent),
+        );
+        map_morton_par::<S, _>(points, center, extent, f)
+    }
+}
+
+/// Morton keys, positions and masses of the points of a [`LinearOctree`], in Morton order.
+struct Sorted<'a, S: Real> {
+    keys: &'a [u64],
+    points: &'a [S::Vector],
+    masses: &'a [S],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LinearOctree;
+    use crate::{
+        direct,
+        initial_conditions::unit_cube,
+        multipole::Multipole,
+        octtree::{Octree, OctreeOptions},
+        test_helpers::walk_fields,
+    };
+    use rand::prelude::*;
+    use ultraviolet::{DVec3, Vec3};
+
+    fn random_points(n: usize, rng: &mut StdRng) -> (Vec<Vec3>, Vec<f32>) {
+        let points = unit_cube::<f32>(n, rng);
+        let masses = (0..n).map(|_| rng.gen()).collect();
+        (points, masses)
+    }
+
+    #[test]
+    fn test_linear_matches_boxed_octree() {
+        let mut rng = StdRng::seed_from_u64(15);
+        let (points, masses) = random_points(3000, &mut rng);
+
+        let options = OctreeOptions {
+            multipole: Multipole::Quadrupole,
+            ..Default::default()
+        };
+        let linear = LinearOctree::construct_with(&points, &masses, options);
+        let boxed = Octree::construct_with(&points, &masses, options);
+
+        assert_eq!(linear.len(), points.len());
+        assert!((linear.total_mass() - boxed.total_mass()).abs() < 1e-3);
+        assert!((linear.com() - boxed.com()).mag() < 1e-5);
+        assert!((linear.quadrupole().xy - boxed.quadrupole().xy).abs() < 1e-2);
+
+        // At theta 0 both walks are exact direct sums.
+        let exact = direct::fields(&points, &masses, 1e-2);
+        let linear_fields = linear.fields(&points, 0., 1e-2);
+        exact
+            .iter()
+            .zip(&linear_fields)
+            .for_each(|(&(acc, pot), &(linear_acc, linear_pot))| {
+                assert!((acc - linear_acc).mag() <= 1e-3 * acc.mag());
+                assert!((pot - linear_pot).abs() <= 1e-4 * pot.abs());
+            });
+
+        // Both layouts see cells of the same size, so make errors of the same size.
+        let error = |fields: &[(Vec3, f32)]| {
+            exact
+                .iter()
+                .zip(fields)
+                .map(|(&(acc, _), &(approx, _))| (acc - approx).mag() / acc.mag())
+                .sum::<f32>()
+                / points.len() as f32
+        };
+        let linear_error = error(&linear.fields(&points, 0.7, 1e-2));
+        let boxed_error = error(&walk_fields(&boxed, &points, 0.7, 1e-2));
+        assert!(linear_error < 5e-3, "{}", linear_error);
+        assert!(
+            linear_error < 2. * boxed_error,
+            "{} {}",
+            linear_error,
+            boxed_error
+        );
+    }
+
+    #[test]
+    fn test_linear_f64_field() {
+        let points: Vec<DVec3> = (0..200)
+            .map(|i| DVec3::new((i as f64).sin(), (i as f64).cos(), i as f64 / 200.))
+            .collect();
+        let masses = vec![1f64; points.len()];
+        let tree = LinearOctree::construct(&points, &masses);
+
+        let target = DVec3::new(5., -3., 2.);
+        let exact = direct::fields(
+            &[points.clone(), vec![target]].concat(),
+            &[masses, vec![0.]].concat(),
+            0.,
+        );
+        let (acc, pot) = tree.field(target, 0., 0.);
+        assert!((acc - exact[200].0).mag() < 1e-12 * acc.mag());
+        assert!((pot - exact[200].1).abs() < 1e-12 * pot.abs());
+    }
+
+    #[test]
+    fn test_linear_coincident_points() {
+        let n = 5000;
+        let point = Vec3::new(0.5, -2., 3.);
+        let tree = LinearOctree::construct(&vec![point; n], &vec![1f32; n]);
+
+        // One chain of nodes down to the deepest Morton level, holding every point in its leaf.
+        assert!(tree.node_count() <= 22, "{}", tree.node_count());
+        assert_eq!(tree.total_mass(), n as f32);
+        assert_eq!(tree.field(point, 0.5, 0.), (Vec3::zero(), 0.));
+
+        let empty = LinearOctree::<f32>::construct(&[], &[]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.field(point, 0.5, 0.), (Vec3::zero(), 0.));
+        assert!(empty.accelerations(&[point], 0.5, 0.).len() == 1);
+    }
+}
@@ -1,34 +1,134 @@
 #![deny(clippy::all)]
 #![forbid(unsafe_code)]
 
-use log::error;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use barnes_hut::{
+    forces::Solver,
+    initial_conditions, integrator,
+    octtree::Octree,
+    render::{Camera, Renderer},
+    simulation::Simulation,
+    snapshot::Snapshot,
+    softening::Softening,
+};
+use log::{error, info};
 use pixels::{Error, Pixels, SurfaceTexture};
+use rand::{rngs::StdRng, SeedableRng};
 use winit::dpi::LogicalSize;
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 use winit_input_helper::WinitInputHelper;
 
-const WIDTH: u32 = 320;
-const HEIGHT: u32 = 240;
-const BOX_SIZE: i16 = 64;
+const USAGE: &str = "\
+Usage: barnes_hut [OPTIONS]
+
+Options:
+    --width <PIXELS>         Framebuffer width [default: 640]
+    --height <PIXELS>        Framebuffer height [default: 480]
+    --particles <N>          Number of particles in the Plummer sphere [default: 10000]
+    --theta <THETA>          Barnes-Hut opening angle [default: 0.7]
+    --softening <LENGTH>     Softening length [default: 0.02]
+    --kernel <NAME>          none, plummer or spline softening [default: plummer]
+    --solver <NAME>          barnes-hut, fmm or group [default: barnes-hut]
+    --dt <DT>                Timestep per frame [default: 0.01]
+    --integrator <NAME>      euler, verlet, rk4 or yoshida [default: verlet]
+    --seed <SEED>            Seed for the initial conditions [default: 0]
+
+Headless options:
+    --output <DIR>           Write frames to DIR instead of opening a window
+    --frames <N>             Number of frames to write [default: 100]
+    --every <N>              Steps between written frames [default: 1]
+    --format <FORMAT>        png, ppm, or snap for binary snapshots [default: png]
+
+Controls:
+    Left mouse drag          Orbit the camera
+    Scroll                   Zoom
+    W, A, S, D               Pan
+    P                        Toggle perspective and orthographic projection
+    C                        Recentre on the centre of mass";
+
+/// Rotation in radians per pixel of mouse drag.
+const ORBIT_SPEED: f32 = 0.005;
+/// Pan per frame as a fraction of the distance to the target.
+const PAN_SPEED: f32 = 0.02;
+/// Zoom factor per scroll line.
+const ZOOM_SPEED: f32 = 0.9;
+
+/// Viewer options, parsed from the command line.
+struct Config {
+    width: u32,
+    height: u32,
+    particles: usize,
+    theta: f32,
+    softening: f32,
+    kernel: String,
+    solver: String,
+    dt: f32,
+    integrator: String,
+    seed: u64,
+    output: Option<PathBuf>,
+    frames: usize,
+    every: usize,
+    format: String,
+}
 
-/// Representation of the application state. In this example, a box will bounce around the screen.
+/// Representation of the application state: a Plummer sphere evolved with the Barnes-Hut tree.
 struct World {
-    box_x: i16,
-    box_y: i16,
-    velocity_x: i16,
-    velocity_y: i16,
+    simulation: Simulation,
+    camera: Camera,
+    renderer: Renderer,
+    dt: f32,
 }
 
-fn main() -> Result<(), Error> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
+    let config = Config::from_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}\n\n{USAGE}");
+        std::process::exit(2);
+    });
+
+    match config.output {
+        Some(ref dir) => run_headless(&config, dir),
+        None => Ok(run_window(&config)?),
+    }
+}
+
+/// Steps the simulation without a display, writing every `config.every`-th frame to `dir`.
+fn run_headless(config: &Config, dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(dir)?;
+    let mut world = World::new(config);
+
+    for frame in 0..config.frames {
+        let path = dir.join(format!("frame_{frame:05}.{}", config.format));
+        if config.format == "snap" {
+            Snapshot::from_simulation(&world.simulation).save(&path)?;
+        } else {
+            world
+                .renderer
+                .draw(&world.simulation.particles.positions, &world.camera);
+            world.renderer.save(&path)?;
+        }
+        info!("Wrote {} at t = {}", path.display(), world.simulation.time);
+
+        world.simulation.run(config.every, config.dt);
+    }
+
+    Ok(())
+}
+
+fn run_window(config: &Config) -> Result<(), Error> {
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
     let window = {
-        let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
+        let size = LogicalSize::new(config.width as f64, config.height as f64);
         WindowBuilder::new()
-            .with_title("Hello Pixels")
+            .with_title("Barnes-Hut")
             .with_inner_size(size)
             .with_min_inner_size(size)
             .build(&event_loop)
@@ -38,9 +138,9 @@ fn main() -> Result<(), Error> {
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH, HEIGHT, surface_texture)?
+        Pixels::new(config.width, config.height, surface_texture)?
     };
-    let mut world = World::new();
+    let mut world = World::new(config);
 
     event_loop.run(move |event, _, control_flow| {
         // Draw the current frame
@@ -70,6 +170,11 @@ fn main() -> Result<(), Error> {
                 }
             }
 
+            handle_camera_input(&mut world.camera, &input);
+            if input.key_pressed(VirtualKeyCode::C) {
+                world.recentre();
+            }
+
             // Update internal state and request a redraw
             world.update();
             window.request_redraw();
@@ -77,50 +182,144 @@ fn main() -> Result<(), Error> {
     });
 }
 
+fn handle_camera_input(camera: &mut Camera, input: &WinitInputHelper) {
+    if input.mouse_held(0) {
+        let (dx, dy) = input.mouse_diff();
+        camera.orbit(-dx * ORBIT_SPEED, dy * ORBIT_SPEED);
+    }
+
+    camera.zoom(ZOOM_SPEED.powf(input.scroll_diff()));
+
+    [
+        (VirtualKeyCode::W, (0., PAN_SPEED)),
+        (VirtualKeyCode::S, (0., -PAN_SPEED)),
+        (VirtualKeyCode::D, (PAN_SPEED, 0.)),
+        (VirtualKeyCode::A, (-PAN_SPEED, 0.)),
+    ]
+    .into_iter()
+    .filter(|&(key, _)| input.key_held(key))
+    .for_each(|(_, (right, up))| camera.pan(right, up));
+
+    if input.key_pressed(VirtualKeyCode::P) {
+        camera.toggle_projection();
+    }
+}
+
+impl Config {
+    /// Parse `--name value` pairs, falling back to the defaults listed in [`USAGE`].
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self {
+            width: 640,
+            height: 480,
+            particles: 10_000,
+            theta: 0.7,
+            softening: 0.02,
+            kernel: "plummer".to_owned(),
+            solver: "barnes-hut".to_owned(),
+            dt: 0.01,
+            integrator: "verlet".to_owned(),
+            seed: 0,
+            output: None,
+            frames: 100,
+            every: 1,
+            format: "png".to_owned(),
+        };
+
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for {flag}"))?;
+
+            match flag.as_str() {
+                "--width" => config.width = parse(&flag, &value)?,
+                "--height" => config.height = parse(&flag, &value)?,
+                "--particles" => config.particles = parse(&flag, &value)?,
+                "--theta" => config.theta = parse(&flag, &value)?,
+                "--softening" => config.softening = parse(&flag, &value)?,
+                "--dt" => config.dt = parse(&flag, &value)?,
+                "--seed" => config.seed = parse(&flag, &value)?,
+                "--integrator" => config.integrator = value,
+                "--kernel" => config.kernel = value,
+                "--solver" => config.solver = value,
+                "--output" => config.output = Some(value.into()),
+                "--frames" => config.frames = parse(&flag, &value)?,
+                "--every" => config.every = parse(&flag, &value)?,
+                "--format" => config.format = value,
+                _ => return Err(format!("Unknown option {flag}")),
+            }
+        }
+
+        if config.width == 0 || config.height == 0 {
+            return Err("Resolution must be non-zero".to_owned());
+        }
+        if integrator::from_name(&config.integrator).is_none() {
+            return Err(format!("Unknown integrator {}", config.integrator));
+        }
+        if Softening::from_name(&config.kernel).is_none() {
+            return Err(format!("Unknown kernel {}", config.kernel));
+        }
+        if Solver::from_name(&config.solver).is_none() {
+            return Err(format!("Unknown solver {}", config.solver));
+        }
+        if !matches!(config.format.as_str(), "png" | "ppm" | "snap") {
+            return Err(format!("Unknown format {}", config.format));
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {flag}: {value}"))
+}
+
 impl World {
-    /// Create a new `World` instance that can draw a moving box.
-    fn new() -> Self {
+    /// Create a new `World` with a Plummer sphere sampled from `config.seed`.
+    fn new(config: &Config) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let (positions, velocities, masses) =
+            initial_conditions::plummer(config.particles, &mut rng);
+        let integrator = integrator::from_name(&config.integrator).unwrap();
+        let mut simulation = Simulation::new(
+            positions,
+            velocities,
+            masses,
+            config.theta,
+            config.softening,
+        )
+        .with_integrator(integrator);
+        simulation.forces.options.softening = Softening::from_name(&config.kernel).unwrap();
+        simulation.forces.solver = Solver::from_name(&config.solver).unwrap();
+        simulation.compute_accelerations();
+
         Self {
-            box_x: 24,
-            box_y: 16,
-            velocity_x: 1,
-            velocity_y: 1,
+            simulation,
+            camera: Camera::new(3.),
+            renderer: Renderer::new(config.width, config.height),
+            dt: config.dt,
         }
     }
 
-    /// Update the `World` internal state; bounce the box around the screen.
+    /// Update the `World` internal state; advance the simulation by one step.
     fn update(&mut self) {
-        if self.box_x <= 0 || self.box_x + BOX_SIZE > WIDTH as i16 {
-            self.velocity_x *= -1;
-        }
-        if self.box_y <= 0 || self.box_y + BOX_SIZE > HEIGHT as i16 {
-            self.velocity_y *= -1;
-        }
+        self.simulation.step(self.dt);
+    }
 
-        self.box_x += self.velocity_x;
-        self.box_y += self.velocity_y;
+    /// Point the camera at the centre of mass of the system.
+    fn recentre(&mut self) {
+        let particles = &self.simulation.particles;
+        self.camera.target = Octree::construct(&particles.positions, &particles.masses).com();
     }
 
-    /// Draw the `World` state to the frame buffer.
+    /// Draw the `World` state to the frame buffer as seen from the camera.
     ///
     /// Assumes the default texture format: `wgpu::TextureFormat::Rgba8UnormSrgb`
-    fn draw(&self, frame: &mut [u8]) {
-        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-            let x = (i % WIDTH as usize) as i16;
-            let y = (i / WIDTH as usize) as i16;
-
-            let inside_the_box = x >= self.box_x
-                && x < self.box_x + BOX_SIZE
-                && y >= self.box_y
-                && y < self.box_y + BOX_SIZE;
-
-            let rgba = if inside_the_box {
-                [0x5e, 0x48, 0xe8, 0xff]
-            } else {
-                [0x48, 0xb2, 0xe8, 0xff]
-            };
-
-            pixel.copy_from_slice(&rgba);
-        }
+    fn draw(&mut self, frame: &mut [u8]) {
+        let rendered = self
+            .renderer
+            .draw(&self.simulation.particles.positions, &self.camera);
+        frame.copy_from_slice(rendered);
     }
 }
@@ -0,0 +1,163 @@
+use std::ops::{Add, AddAssign};
+
+use crate::{
+    real::{Real, Vector, Wide, WideVector},
+    softening::Softening,
+};
+
+/// Highest multipole order kept per node of an [`Octree`](crate::octtree::Octree).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Multipole {
+    /// Total mass at the centre of mass only.
+    #[default]
+    Monopole,
+    /// Adds the traceless quadrupole about the centre of mass.
+    Quadrupole,
+}
+
+/// Traceless quadrupole moment `sum m (3 x x^T - |x|^2 I)` of a mass distribution about an
+/// expansion centre, stored as the six independent components of the symmetric tensor.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quadrupole<S: Real> {
+    pub xx: S,
+    pub yy: S,
+    pub zz: S,
+    pub xy: S,
+    pub xz: S,
+    pub yz: S,
+}
+
+impl<S: Real> Quadrupole<S> {
+    /// Moment of a point `mass` at `offset` from the expansion centre. Adding this for each
+    /// child's mass at its centre of mass is the parallel-axis shift of the child's moment.
+    pub fn point(mass: S, offset: S::Vector) -> Self {
+        let three = S::from_f64(3.);
+        let r_sq = offset.mag_sq();
+        let (x, y, z) = (offset[0], offset[1], offset[2]);
+
+        Self {
+            xx: mass * (three * x * x - r_sq),
+            yy: mass * (three * y * y - r_sq),
+            zz: mass * (three * z * z - r_sq),
+            xy: mass * three * x * y,
+            xz: mass * three * x * z,
+            yz: mass * three * y * z,
+        }
+    }
+
+    /// Summed moments of a SIMD lane of points at `offsets` from the expansion centre.
+    pub fn lane(masses: S::Wide, offsets: S::WideVector) -> Self {
+        let three = S::Wide::splat(S::from_f64(3.));
+        let r_sq = offsets.mag_sq();
+        let (x, y, z) = (offsets.x(), offsets.y(), offsets.z());
+        let three_m = masses * three;
+
+        Self {
+            xx: (three_m * x * x - masses * r_sq).reduce_add(),
+            yy: (three_m * y * y - masses * r_sq).reduce_add(),
+            zz: (three_m * z * z - masses * r_sq).reduce_add(),
+            xy: (three_m * x * y).reduce_add(),
+            xz: (three_m * x * z).reduce_add(),
+            yz: (three_m * y * z).reduce_add(),
+        }
+    }
+
+    /// The matrix-vector product `Q v`.
+    pub fn apply(&self, v: S::Vector) -> S::Vector {
+        S::Vector::new(
+            self.xx * v[0] + self.xy * v[1] + self.xz * v[2],
+            self.xy * v[0] + self.yy * v[1] + self.yz * v[2],
+            self.xz * v[0] + self.yz * v[1] + self.zz * v[2],
+        )
+    }
+
+    /// Acceleration and potential (with `G = 1`) at `r` from the expansion centre, where
+    /// `inv_r` is the (possibly softened) inverse distance.
+    pub fn field(&self, r: S::Vector, inv_r: S) -> (S::Vector, S) {
+        let inv_r2 = inv_r * inv_r;
+        let inv_r5 = inv_r2 * inv_r2 * inv_r;
+
+        let q_r = self.apply(r);
+        let r_q_r = r.dot(q_r);
+
+        (
+            q_r * inv_r5 - r * (S::from_f64(2.5) * r_q_r * inv_r5 * inv_r2),
+            -S::from_f64(0.5) * r_q_r * inv_r5,
+        )
+    }
+}
+
+impl<S: Real> Add for Quadrupole<S> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            xx: self.xx + rhs.xx,
+            yy: self.yy + rhs.yy,
+            zz: self.zz + rhs.zz,
+            xy: self.xy + rhs.xy,
+            xz: self.xz + rhs.xz,
+            yz: self.yz + rhs.yz,
+        }
+    }
+}
+
+impl<S: Real> AddAssign for Quadrupole<S> {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+/// Acceleration and potential at `point` from a cell of `mass` at `com` with `quadrupole` about
+/// `com`, expanded up to `order` and softened by `kernel` with length `softening`.
+pub(crate) fn cell_field<S: Real>(
+    order: Multipole,
+    mass: S,
+    com: S::Vector,
+    quadrupole: &Quadrupole<S>,
+    point: S::Vector,
+    softening: S,
+    kernel: Softening,
+) -> (S::Vector, S) {
+    let diff = com - point;
+    let (inv_r, inv_r3) = kernel.factors(diff.mag_sq(), softening);
+
+    let monopole = (diff * (mass * inv_r3), -mass * inv_r);
+
+    match order {
+        Multipole::Monopole => monopole,
+        Multipole::Quadrupole => {
+            let quadrupole = quadrupole.field(-diff, inv_r);
+            (monopole.0 + quadrupole.0, monopole.1 + quadrupole.1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Quadrupole;
+    use ultraviolet::DVec3;
+
+    #[test]
+    fn test_quadrupole_far_field() {
+        // Two unit masses on the x axis, expanded about their centre of mass.
+        let offsets = [DVec3::new(-0.5, 0., 0.), DVec3::new(0.5, 0., 0.)];
+        let quadrupole = offsets
+            .iter()
+            .fold(Quadrupole::default(), |q, &x| q + Quadrupole::point(1., x));
+        assert_eq!(quadrupole.xx + quadrupole.yy + quadrupole.zz, 0.);
+
+        let r = DVec3::new(3., 4., 12.);
+        let (acc, pot) = quadrupole.field(r, 1. / r.mag());
+        let (acc, pot) = (acc + r * (-2. / r.mag().powi(3)), pot - 2. / r.mag());
+
+        let exact_acc = offsets
+            .iter()
+            .fold(DVec3::zero(), |a, &x| a + (x - r) / (x - r).mag().powi(3));
+        let exact_pot = offsets.iter().map(|&x| -1. / (x - r).mag()).sum::<f64>();
+
+        // The octupole vanishes by symmetry, so the error is of hexadecapole order.
+        assert!((acc - exact_acc).mag() < 1e-4 * exact_acc.mag());
+        assert!((pot - exact_pot).abs() < 1e-4 * exact_pot.abs());
+    }
+}
@@ -1,45 +1,136 @@
-use ultraviolet::{Vec3, Vec3x8, f32x8};
+use std::borrow::Cow;
 
-#[derive(Clone, Debug)]
-pub struct Octree {
-    point: ([usize; 8], Vec3x8, f32x8),
-    count: u8,
-    com: Vec3,
-    total_mass: f32,
-    children: Option<Box<[Octree; 8]>>,
-    center: Vec3,
-    extent: Vec3,
+use rayon::prelude::*;
+
+use crate::{
+    direct::lane_field,
+    ewald,
+    kernel::Gravity,
+    multipole::{cell_field, Multipole, Quadrupole},
+    real::{Real, Vector, Wide, WideVector},
+    softening::Softening,
+};
+
+/// Index, position, mass and softening length of a point stored in an [`Octree`].
+type Stored<S> = (usize, <S as Real>::Vector, S, S);
+
+/// Indices, positions, masses and softening lengths of up to [`Real::LANES`] points, padded with
+/// zero mass at the origin.
+pub(crate) type Lane<S> = (
+    <S as Real>::Indices,
+    <S as Real>::WideVector,
+    <S as Real>::Wide,
+    <S as Real>::Wide,
+);
+
+/// Nodes with fewer points than this left to insert below them are built serially.
+const PARALLEL_THRESHOLD: usize = 4096;
+
+/// Nodes at least this deep compute their children's moments serially.
+const PARALLEL_DEPTH: u32 = 3;
+
+/// Largest number of points, consecutive in Morton order, in each parallel task of the batch
+/// field queries. Without periodic boundaries, the points of a task share one interaction list.
+const FIELD_CHUNK: usize = 256;
+
+/// Options for building an [`Octree`].
+///
+/// A node at `max_depth`, or no larger than `min_size` along every axis, is never split and
+/// instead keeps any number of points in its bucket. This bounds the depth of the tree when many
+/// points coincide or nearly coincide. `multipole` sets the order of the moments computed for
+/// every node and used for accepted cells in the force walk.
+///
+/// The root cell tightly bounds the points unless `cubic` is set, in which case it is a cube with
+/// the largest extent of the bounding box along every axis, so all cells are cubes and the
+/// opening criterion sees the same size in every direction. The root is then grown by `padding`
+/// times its size on every side, which keeps points on the bounding box safely inside it.
+///
+/// Every node holds up to `bucket_lanes` SIMD lanes of [`Real::LANES`] points before points are
+/// passed on to its children. By default a node keeps the first points inserted into it even
+/// once it has children. With `leaf_only` set, a full node instead moves all of its points into
+/// its children when it splits, so only leaves hold points and the tree depends only on the set
+/// of points and not on their order.
+///
+/// `softening` is the kernel softening every interaction in the force walk, see [`Softening`].
+///
+/// With `periodic` set to a box size `L`, space is periodic with period `L` along every axis. The
+/// root cell is then the box `[0, L)^3` whatever the points, and points are wrapped into it when
+/// inserted. The force walk measures every distance to the nearest periodic image, only accepts
+/// cells whose nearest image lies within half of the box of the target, and adds the field of
+/// all further images from an Ewald correction, see [`ewald::correction`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OctreeOptions<S: Real = f32> {
+    pub max_depth: u32,
+    pub min_size: S,
+    pub multipole: Multipole,
+    pub cubic: bool,
+    pub padding: S,
+    pub bucket_lanes: usize,
+    pub leaf_only: bool,
+    pub softening: Softening,
+    pub periodic: Option<S>,
 }
 
-impl Octree {
-    pub fn construct(points: &[Vec3], masses: &[f32]) -> Self {
-        let mut octree = Octree::default();
+impl<S: Real> OctreeOptions<S> {
+    /// Number of points a node holds before it is split.
+    pub fn bucket_size(&self) -> usize {
+        self.bucket_lanes.max(1) * S::LANES
+    }
+}
 
-        assert_eq!(
-            points.len(),
-            masses.len(),
-            "Length of given points not equal to length of given masses"
-        );
-        if points.len() == 0 {
-            return octree;
+impl<S: Real> Default for OctreeOptions<S> {
+    fn default() -> Self {
+        Self {
+            // Deeper cells are below `f32` resolution relative to the root.
+            max_depth: 24,
+            min_size: S::ZERO,
+            multipole: Multipole::Monopole,
+            cubic: false,
+            padding: S::ZERO,
+            bucket_lanes: 1,
+            leaf_only: false,
+            softening: Softening::Plummer,
+            periodic: None,
         }
+    }
+}
 
-        let min_bound = points
-            .iter()
-            .copied()
-            .reduce(Vec3::min_by_component)
-            .unwrap();
-        let max_bound = points
-            .iter()
-            .copied()
-            .reduce(Vec3::max_by_component)
-            .unwrap();
+/// Barnes-Hut octree over points of precision `S`, either `f32` or `f64`.
+#[derive(Clone, Debug)]
+pub struct Octree<S: Real = f32> {
+    point: Lane<S>,
+    /// Further lanes of points, used by nodes holding more than one lane.
+    overflow: Vec<Lane<S>>,
+    count: usize,
+    /// Centre of the absolute masses, which is the centre of mass unless some are negative.
+    com: S::Vector,
+    total_mass: S,
+    /// Total and centre of the positive masses, and of the negative masses, e.g. of signed
+    /// charges. Zero if there are none.
+    positive: (S, S::Vector),
+    negative: (S, S::Vector),
+    /// Quadrupole about `com`, zero unless enabled in the options.
+    quadrupole: Quadrupole<S>,
+    /// Largest softening length of the points in this node and its descendants.
+    softening: S,
+    children: Option<Box<[Octree<S>; 8]>>,
+    center: S::Vector,
+    extent: S::Vector,
+    depth: u32,
+    options: OctreeOptions<S>,
+    /// Length of the fields of every point, indexed as inserted: the number of points the tree
+    /// was built with, or one more than the largest index added to this node since.
+    index_count: usize,
+}
 
-        octree.center = (min_bound + max_bound) / 2.0;
-        octree.extent = max_bound - min_bound;
+impl<S: Real> Octree<S> {
+    pub fn construct(points: &[S::Vector], masses: &[S]) -> Self {
+        Self::construct_with(points, masses, OctreeOptions::default())
+    }
 
-        // octree.center -= octree.extent * EPSILON;
-        // octree.extent += octree.extent * 2. * EPSILON;
+    pub fn construct_with(points: &[S::Vector], masses: &[S], options: OctreeOptions<S>) -> Self {
+        let points = &wrap_all(points, &options);
+        let mut octree = Self::root(points, masses, options);
 
         points
             .iter()
@@ -53,60 +144,394 @@ impl Octree {
         octree
     }
 
-    pub fn add_point(&mut self, idx: usize, point: Vec3, mass: f32) {
-        if self.count < 8 {
-            let count = self.count as usize;
+    /// Builds the same tree as [`Octree::construct`], but partitions the points into octants and
+    /// builds the eight subtrees of every large enough node concurrently.
+    pub fn construct_par(points: &[S::Vector], masses: &[S]) -> Self {
+        Self::construct_par_with(points, masses, OctreeOptions::default())
+    }
+
+    /// Parallel form of [`Octree::construct_with`], see [`Octree::construct_par`].
+    pub fn construct_par_with(
+        points: &[S::Vector],
+        masses: &[S],
+        options: OctreeOptions<S>,
+    ) -> Self {
+        let points = &wrap_all(points, &options);
+        let mut octree = Self::root(points, masses, options);
+
+        let indices: Vec<usize> = (0..points.len()).collect();
+        octree.insert_par(&indices, points, masses);
+
+        octree.compute_par();
+        octree
+    }
+
+    /// Empty root node bounding all of `points`.
+    fn root(points: &[S::Vector], masses: &[S], options: OctreeOptions<S>) -> Self {
+        let mut octree = Octree {
+            options,
+            index_count: points.len(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            points.len(),
+            masses.len(),
+            "Length of given points not equal to length of given masses"
+        );
+        if let Some(box_size) = options.periodic {
+            octree.center = S::Vector::broadcast(box_size / S::from_f64(2.0));
+            octree.extent = S::Vector::broadcast(box_size);
+            return octree;
+        }
+        if points.is_empty() {
+            return octree;
+        }
+
+        (octree.center, octree.extent) = bounds(points, &options);
+
+        // octree.center -= octree.extent * EPSILON;
+        // octree.extent += octree.extent * 2. * EPSILON;
+
+        octree
+    }
+
+    /// Inserts the points at `indices`, in order, exactly as repeated calls to
+    /// [`Octree::add_point`] would, building the subtrees of large nodes in parallel.
+    fn insert_par(&mut self, indices: &[usize], points: &[S::Vector], masses: &[S]) {
+        let bucket_size = self.options.bucket_size();
+        let split = if !self.can_split() || self.count + indices.len() <= bucket_size {
+            indices.len()
+        } else if self.options.leaf_only {
+            0
+        } else {
+            bucket_size.saturating_sub(self.count).min(indices.len())
+        };
 
-            self.point.0[count] = idx;
+        let (own, rest) = indices.split_at(split);
+        own.iter()
+            .for_each(|&i| self.add_point(i, points[i], masses[i]));
 
-            let mut x_array = self.point.1.x.to_array();
-            let mut y_array = self.point.1.y.to_array();
-            let mut z_array = self.point.1.z.to_array();
-            let mut mass_array = self.point.2.to_array();
+        if rest.len() < PARALLEL_THRESHOLD || self.count > 0 && self.options.leaf_only {
+            rest.iter()
+                .for_each(|&i| self.add_point(i, points[i], masses[i]));
+            return;
+        }
 
-            x_array[count] = point.x;
-            y_array[count] = point.y;
-            z_array[count] = point.z;
-            mass_array[count] = mass;
+        let mut octants: [Vec<usize>; 8] = Default::default();
+        rest.iter()
+            .for_each(|&i| octants[self.child_index(points[i])].push(i));
 
-            self.point.1.x = f32x8::new(x_array);
-            self.point.1.y = f32x8::new(y_array);
-            self.point.1.z = f32x8::new(z_array);
-            self.point.2 = f32x8::new(mass_array);
+        self.get_child(0);
+        let children = self.children.as_mut().unwrap();
+        children
+            .par_iter_mut()
+            .zip(octants.par_iter())
+            .for_each(|(child, octant)| child.insert_par(octant, points, masses));
+    }
+
+    pub fn add_point(&mut self, idx: usize, point: S::Vector, mass: S) {
+        self.index_count = self.index_count.max(idx + 1);
+        self.insert(idx, wrap(point, &self.options), mass, S::ZERO);
+    }
 
+    /// Inserts a point with its own softening length, see [`Octree::set_softenings`].
+    fn insert(&mut self, idx: usize, point: S::Vector, mass: S, softening: S) {
+        let has_room = self.count < self.options.bucket_size()
+            && !(self.options.leaf_only && self.children.is_some());
+
+        if has_room || !self.can_split() {
+            self.set_slot(self.count, (idx, point, mass, softening));
             self.count += 1;
         } else {
-            let child_idx = {
-                let diff = point - self.center;
-                (diff.x.is_sign_positive() as usize) << 2
-                    | (diff.y.is_sign_positive() as usize) << 1
-                    | (diff.z.is_sign_positive() as usize)
-            };
+            if self.options.leaf_only && self.count > 0 {
+                self.split();
+            }
+
+            let child_idx = self.child_index(point);
+            self.get_child(child_idx)
+                .insert(idx, point, mass, softening);
+        }
+    }
+
+    /// Stores a point in slot `k` of this node's lanes, adding a lane if needed.
+    fn set_slot(&mut self, k: usize, (idx, point, mass, softening): Stored<S>) {
+        let (lane, slot) = (k / S::LANES, k % S::LANES);
+        if lane > self.overflow.len() {
+            self.overflow.push(Default::default());
+        }
+
+        let lane = match lane {
+            0 => &mut self.point,
+            _ => &mut self.overflow[lane - 1],
+        };
+
+        lane.0.as_mut()[slot] = idx;
+
+        let mut x_array = lane.1.x().to_array();
+        let mut y_array = lane.1.y().to_array();
+        let mut z_array = lane.1.z().to_array();
+        let mut mass_array = lane.2.to_array();
+        let mut softening_array = lane.3.to_array();
+
+        x_array.as_mut()[slot] = point[0];
+        y_array.as_mut()[slot] = point[1];
+        z_array.as_mut()[slot] = point[2];
+        mass_array.as_mut()[slot] = mass;
+        softening_array.as_mut()[slot] = softening;
+
+        lane.1 = S::WideVector::new(
+            S::Wide::new(x_array),
+            S::Wide::new(y_array),
+            S::Wide::new(z_array),
+        );
+        lane.2 = S::Wide::new(mass_array);
+        lane.3 = S::Wide::new(softening_array);
+    }
+
+    /// The point in slot `k` of this node's lanes.
+    fn slot(&self, k: usize) -> Stored<S> {
+        let (slot, (indices, positions, masses, softenings)) = match k / S::LANES {
+            0 => (k, &self.point),
+            lane => (k % S::LANES, &self.overflow[lane - 1]),
+        };
+        let [x, y, z] = [positions.x(), positions.y(), positions.z()].map(Wide::to_array);
+        let point = S::Vector::new(x.as_ref()[slot], y.as_ref()[slot], z.as_ref()[slot]);
+
+        (
+            indices.as_ref()[slot],
+            point,
+            masses.to_array().as_ref()[slot],
+            softenings.to_array().as_ref()[slot],
+        )
+    }
+
+    /// Removes the point in slot `k` of this node's lanes, moving the last point into its place.
+    fn remove_slot(&mut self, k: usize) {
+        let last = self.count - 1;
+        if k != last {
+            self.set_slot(k, self.slot(last));
+        }
+        self.set_slot(last, (0, S::Vector::zero(), S::ZERO, S::ZERO));
+
+        self.count = last;
+        self.overflow
+            .truncate(self.count.saturating_sub(1) / S::LANES);
+    }
+
+    /// Moves all points stored in this node into its children.
+    fn split(&mut self) {
+        let points: Vec<_> = self.points().collect();
+
+        self.point = Default::default();
+        self.overflow.clear();
+        self.count = 0;
+
+        points
+            .into_iter()
+            .for_each(|(idx, point, mass, softening)| {
+                let child_idx = self.child_index(point);
+                self.get_child(child_idx)
+                    .insert(idx, point, mass, softening);
+            });
+    }
+
+    /// Removes the point with index `idx` at `point`, returning its mass, or `None` if it is not
+    /// in the tree. Nodes left empty, or in leaf only mode with few enough points to be a leaf,
+    /// are merged back into their parents. Moments are only updated by [`Octree::compute`].
+    pub fn remove_point(&mut self, idx: usize, point: S::Vector) -> Option<S> {
+        let point = wrap(point, &self.options);
+        let slot = self.points().position(|(i, ..)| i == idx);
+        let mass = match slot {
+            Some(k) => {
+                let (_, _, mass, _) = self.slot(k);
+                self.remove_slot(k);
+                mass
+            }
+            None => {
+                let child_idx = self.child_index(point);
+                self.children.as_mut()?[child_idx].remove_point(idx, point)?
+            }
+        };
+
+        self.collapse();
+        Some(mass)
+    }
+
+    /// Moves the points in the tree to their new positions in `points`, indexed as when they were
+    /// inserted, and updates the moments. Called on the root, this refits the tree in place:
+    /// points which are still inside their node only have their positions updated, and points
+    /// which have left it are removed and reinserted from the root. The whole tree is rebuilt if
+    /// any point has left the root cell. In a periodic tree, points are wrapped back into the box
+    /// instead.
+    pub fn update_positions(&mut self, points: &[S::Vector]) {
+        let points = &wrap_all(points, &self.options);
+        let mut escaped = Vec::new();
+        self.refit(points, &mut escaped);
+
+        let half_extent = self.extent / S::from_f64(2.0);
+        let inside = |point: S::Vector| {
+            let disp = (point - self.center).abs();
+            (0..3).all(|axis| disp[axis] <= half_extent[axis])
+        };
+
+        if escaped.iter().all(|&(idx, ..)| inside(points[idx])) {
+            escaped.into_iter().for_each(|(idx, _, mass, softening)| {
+                self.insert(idx, points[idx], mass, softening)
+            });
+        } else {
+            let mut stored = Vec::new();
+            self.collect_points(&mut stored);
+            stored.extend(escaped);
+            stored.sort_unstable_by_key(|&(idx, ..)| idx);
+
+            let positions: Vec<S::Vector> = stored.iter().map(|&(idx, ..)| points[idx]).collect();
+            let masses: Vec<S> = stored.iter().map(|&(_, _, mass, _)| mass).collect();
+            let index_count = self.index_count;
+            *self = Self::root(&positions, &masses, self.options);
+            self.index_count = index_count;
+            stored.into_iter().for_each(|(idx, _, mass, softening)| {
+                self.insert(idx, points[idx], mass, softening)
+            });
+        }
+
+        self.compute_par();
+    }
+
+    /// Updates the positions of the points in this subtree, removing those which have left their
+    /// node into `escaped`.
+    fn refit(&mut self, points: &[S::Vector], escaped: &mut Vec<Stored<S>>) {
+        let half_extent = self.extent / S::from_f64(2.0);
 
-            self.get_child(child_idx).add_point(idx, point, mass);
+        // Backwards, so points moved into removed slots have already been updated.
+        (0..self.count).rev().for_each(|k| {
+            let (idx, _, mass, softening) = self.slot(k);
+            let point = points[idx];
+
+            let disp = (point - self.center).abs();
+            if (0..3).all(|axis| disp[axis] <= half_extent[axis]) {
+                self.set_slot(k, (idx, point, mass, softening));
+            } else {
+                self.remove_slot(k);
+                escaped.push((idx, point, mass, softening));
+            }
+        });
+
+        if let Some(ref mut children) = self.children {
+            children
+                .iter_mut()
+                .for_each(|child| child.refit(points, escaped));
+        }
+
+        self.collapse();
+    }
+
+    /// Merges leaf children back into this node if they are all empty, or in leaf only mode if
+    /// they hold no more points than fit in one bucket.
+    fn collapse(&mut self) {
+        let children = match self.children {
+            Some(ref children) if children.iter().all(|child| child.children.is_none()) => children,
+            _ => return,
+        };
+
+        let count: usize = children.iter().map(|child| child.count).sum();
+        if count == 0 || self.options.leaf_only && self.count + count <= self.options.bucket_size()
+        {
+            let points: Vec<_> = children.iter().flat_map(Octree::points).collect();
+            self.children = None;
+            points
+                .into_iter()
+                .for_each(|(idx, point, mass, softening)| self.insert(idx, point, mass, softening));
+        }
+    }
+
+    /// Appends all points in this subtree to `stored`.
+    fn collect_points(&self, stored: &mut Vec<Stored<S>>) {
+        stored.extend(self.points());
+        self.children
+            .iter()
+            .flat_map(|children| children.iter())
+            .for_each(|child| child.collect_points(stored));
+    }
+
+    /// The points stored in this node.
+    pub(crate) fn points(&self) -> impl Iterator<Item = Stored<S>> + '_ {
+        self.lanes()
+            .flat_map(|(indices, positions, masses, softenings)| {
+                let [x, y, z] = [positions.x(), positions.y(), positions.z()].map(Wide::to_array);
+                let [masses, softenings] = [masses, softenings].map(|w| w.to_array());
+
+                (0..S::LANES).map(move |slot| {
+                    let point =
+                        S::Vector::new(x.as_ref()[slot], y.as_ref()[slot], z.as_ref()[slot]);
+                    (
+                        indices.as_ref()[slot],
+                        point,
+                        masses.as_ref()[slot],
+                        softenings.as_ref()[slot],
+                    )
+                })
+            })
+            .take(self.count)
+    }
+
+    /// Sets the softening length of every point in the tree to its entry in `softenings`,
+    /// indexed as when the points were inserted. Each interaction in the force walk is softened
+    /// with the larger of the lengths of the target and of the source point or cell, where the
+    /// length of a cell is the largest of its points.
+    pub fn set_softenings(&mut self, softenings: &[S]) {
+        (0..self.count).for_each(|k| {
+            let (idx, point, mass, _) = self.slot(k);
+            self.set_slot(k, (idx, point, mass, softenings[idx]));
+        });
+
+        if let Some(ref mut children) = self.children {
+            children
+                .par_iter_mut()
+                .for_each(|child| child.set_softenings(softenings));
         }
+
+        self.compute_softening();
+    }
+
+    /// Computes the largest softening length in this node, assuming the children are up to date.
+    fn compute_softening(&mut self) {
+        let own = self
+            .points()
+            .fold(S::ZERO, |max, (_, _, _, softening)| max.max(softening));
+
+        self.softening = self
+            .children
+            .iter()
+            .flat_map(|children| children.iter())
+            .fold(own, |max, child| max.max(child.softening));
+    }
+
+    /// Index of the child whose octant contains `point`.
+    fn child_index(&self, point: S::Vector) -> usize {
+        let diff = point - self.center;
+        (diff[0].is_sign_positive() as usize) << 2
+            | (diff[1].is_sign_positive() as usize) << 1
+            | (diff[2].is_sign_positive() as usize)
     }
 
-    pub fn get_child(&mut self, idx: usize) -> &mut Octree {
+    pub fn get_child(&mut self, idx: usize) -> &mut Octree<S> {
         if let Some(ref mut children) = self.children {
             &mut children[idx]
         } else {
-            let offsets = [
-                Vec3::new(-1., -1., -1.),
-                Vec3::new(-1., -1., 1.),
-                Vec3::new(-1., 1., -1.),
-                Vec3::new(-1., 1., 1.),
-                Vec3::new(1., -1., -1.),
-                Vec3::new(1., -1., 1.),
-                Vec3::new(1., 1., -1.),
-                Vec3::new(1., 1., 1.),
-            ];
-
-            let mut children: [Octree; 8] = Default::default();
+            // Child `i` lies on the positive side of axis `a` if bit `2 - a` of `i` is set.
+            let offset = |i: usize| {
+                let sign = |bit: usize| if i & bit != 0 { S::ONE } else { -S::ONE };
+                S::Vector::new(sign(4), sign(2), sign(1))
+            };
+
+            let mut children: [Octree<S>; 8] = Default::default();
 
             children.iter_mut().enumerate().for_each(|(i, child)| {
-                child.extent = self.extent / 2.0;
-                child.center = self.center + self.extent * offsets[i] / 4.0;
+                child.extent = self.extent / S::from_f64(2.0);
+                child.center = self.center + self.extent * offset(i) / S::from_f64(4.0);
+                child.depth = self.depth + 1;
+                child.options = self.options;
             });
 
             self.children = Some(Box::new(children));
@@ -114,38 +539,222 @@ impl Octree {
         }
     }
 
-    pub fn compute(&mut self) {
-        let (mut com, mut total_mass) = (Vec3::zero(), 0.);
+    /// Whether this node may be subdivided under its [`OctreeOptions`].
+    fn can_split(&self) -> bool {
+        self.depth < self.options.max_depth && self.extent.component_max() > self.options.min_size
+    }
 
-        let (_, self_com, self_mass) = self.point;
-        com += {
-            let tmp = self_com * self_mass;
-            Vec3::new(tmp.x.reduce_add(), tmp.y.reduce_add(), tmp.z.reduce_add())
-        };
-        total_mass += self_mass.reduce_add();
+    pub(crate) fn lanes(&self) -> impl Iterator<Item = &Lane<S>> {
+        std::iter::once(&self.point).chain(&self.overflow)
+    }
 
+    pub fn compute(&mut self) {
         if let Some(ref mut children) = self.children {
             children.iter_mut().for_each(Octree::compute);
+        }
+        self.compute_moments();
+    }
+
+    /// Parallel form of [`Octree::compute`], computing the children of shallow nodes
+    /// concurrently.
+    pub fn compute_par(&mut self) {
+        if let Some(ref mut children) = self.children {
+            if self.depth < PARALLEL_DEPTH {
+                children.par_iter_mut().for_each(Octree::compute_par);
+            } else {
+                children.iter_mut().for_each(Octree::compute);
+            }
+        }
+        self.compute_moments();
+    }
+
+    /// Computes the moments of this node, assuming those of the children are up to date.
+    fn compute_moments(&mut self) {
+        // Sums of the positive and of the negative masses, and of their moments.
+        let mut sums = [(S::ZERO, S::Vector::zero()); 2];
+
+        self.lanes().for_each(|&(_, lane_com, lane_mass, _)| {
+            let positive = lane_mass.max(S::Wide::splat(S::ZERO));
+            [positive, lane_mass - positive]
+                .into_iter()
+                .zip(&mut sums)
+                .for_each(|(mass, sum)| {
+                    sum.0 += mass.reduce_add();
+                    sum.1 += (lane_com * mass).reduce_add();
+                });
+        });
+
+        if let Some(ref children) = self.children {
+            children.iter().for_each(|child| {
+                [child.positive, child.negative]
+                    .into_iter()
+                    .zip(&mut sums)
+                    .for_each(|((mass, center), sum)| {
+                        sum.0 += mass;
+                        sum.1 += center * mass;
+                    });
+            });
+        }
+
+        let center = |(mass, moment): (S, S::Vector)| match mass == S::ZERO {
+            true => S::Vector::zero(),
+            false => moment / mass,
+        };
+        let [positive, negative] = sums;
+        self.positive = (positive.0, center(positive));
+        self.negative = (negative.0, center(negative));
+
+        self.total_mass = positive.0 + negative.0;
+        self.com = center((positive.0 - negative.0, positive.1 - negative.1));
 
-            let (child_com, child_mass) = children.iter().fold((Vec3::zero(), 0.), |a, b| {
-                (a.0 + b.total_mass * b.com, a.1 + b.total_mass)
+        if self.options.multipole == Multipole::Quadrupole {
+            self.compute_quadrupole();
+        }
+        self.compute_softening();
+    }
+
+    /// Sums the quadrupole of the points in this node and of the children shifted to `com`,
+    /// assuming `com` and the children's moments are up to date.
+    fn compute_quadrupole(&mut self) {
+        let com = S::WideVector::splat(self.com);
+        let mut quadrupole = self
+            .lanes()
+            .fold(Quadrupole::default(), |q, &(_, positions, masses, _)| {
+                q + Quadrupole::lane(masses, positions - com)
             });
 
-            com += child_com;
-            total_mass += child_mass;
+        if let Some(ref children) = self.children {
+            children.iter().for_each(|child| {
+                quadrupole +=
+                    child.quadrupole + Quadrupole::point(child.total_mass, child.com - self.com);
+            });
+        }
+
+        self.quadrupole = quadrupole;
+    }
+
+    /// Quadrupole moment about the centre of mass, zero unless enabled in the options.
+    pub fn quadrupole(&self) -> Quadrupole<S> {
+        self.quadrupole
+    }
+
+    /// Centre of mass of all points in this node and its descendants, or of their absolute
+    /// masses if some are negative.
+    pub fn com(&self) -> S::Vector {
+        self.com
+    }
+
+    /// Total mass of all points in this node and its descendants.
+    pub fn total_mass(&self) -> S {
+        self.total_mass
+    }
+
+    /// Total and centre of the positive masses in this node and its descendants.
+    pub fn positive(&self) -> (S, S::Vector) {
+        self.positive
+    }
+
+    /// Total and centre of the negative masses in this node and its descendants.
+    pub fn negative(&self) -> (S, S::Vector) {
+        self.negative
+    }
+
+    /// Checks that every point stored in the tree lies inside the box of its node, and that the
+    /// mass and centre of mass of every node match the points it contains up to rounding.
+    /// Returns a description of the first inconsistency found.
+    pub fn validate(&self) -> Result<(), String> {
+        self.validate_node().map(|_| ())
+    }
+
+    /// Validates this subtree, returning the mass, absolute mass, and absolute mass weighted sum
+    /// of positions of its points.
+    fn validate_node(&self) -> Result<(S, S, S::Vector), String> {
+        let half_extent = self.extent / S::from_f64(2.0);
+        let (mut mass, mut abs_mass, mut moment) = (S::ZERO, S::ZERO, S::Vector::zero());
+
+        for (idx, point, point_mass, _) in self.points() {
+            let disp = (point - self.center).abs();
+            if (0..3).any(|axis| disp[axis] > half_extent[axis]) {
+                return Err(format!(
+                    "Point {} at {:?} lies outside node at {:?} with extent {:?}",
+                    idx, point, self.center, self.extent
+                ));
+            }
+
+            mass += point_mass;
+            abs_mass += point_mass.abs();
+            moment += point * point_mass.abs();
+        }
+
+        for child in self.children.iter().flat_map(|children| children.iter()) {
+            let (child_mass, child_abs_mass, child_moment) = child.validate_node()?;
+            mass += child_mass;
+            abs_mass += child_abs_mass;
+            moment += child_moment;
         }
 
-        self.total_mass = total_mass;
-        self.com = if total_mass == 0. {
-            Vec3::zero()
+        let tolerance = S::from_f64(1e-4);
+        let com = if abs_mass == S::ZERO {
+            S::Vector::zero()
         } else {
-            com / total_mass
+            moment / abs_mass
         };
+        if (self.total_mass - mass).abs() > tolerance * abs_mass {
+            return Err(format!(
+                "Node at {:?} has mass {:?} but contains mass {:?}",
+                self.center, self.total_mass, mass
+            ));
+        }
+        if (self.com - com).mag() > tolerance * self.extent.component_max() {
+            return Err(format!(
+                "Node at {:?} has centre of mass {:?} but its points have {:?}",
+                self.center, self.com, com
+            ));
+        }
+
+        Ok((mass, abs_mass, moment))
+    }
+
+    /// Centre of this node's box.
+    pub fn center(&self) -> S::Vector {
+        self.center
+    }
+
+    /// Size of this node's box along each axis.
+    pub fn extent(&self) -> S::Vector {
+        self.extent
+    }
+
+    /// Depth of this node below the root.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// The eight children of this node, or `None` for a leaf.
+    pub fn children(&self) -> Option<&[Octree<S>; 8]> {
+        self.children.as_deref()
+    }
+
+    /// Options the tree was built with.
+    pub fn options(&self) -> &OctreeOptions<S> {
+        &self.options
+    }
+
+    /// Length of the fields of all points of the tree, see [`Octree::fmm_fields`].
+    pub(crate) fn index_count(&self) -> usize {
+        self.index_count
     }
 
-    pub fn find(&self, idx: usize) -> Option<&Octree> {
-        if self.point.0.contains(&idx) {
-            return Some(&self);
+    /// Largest softening length of the points in this node and its descendants.
+    pub(crate) fn softening_length(&self) -> S {
+        self.softening
+    }
+
+    /// Node storing the point with index `idx`, searching the whole tree. Prefer
+    /// [`Octree::locate`] when the position of the point is known.
+    pub fn find(&self, idx: usize) -> Option<&Octree<S>> {
+        if self.holds(idx) {
+            return Some(self);
         }
 
         self.children
@@ -153,26 +762,423 @@ impl Octree {
             .iter()
             .find_map(|tree| tree.find(idx))
     }
+
+    /// Node storing the point with index `idx` inserted at `point`, found in time proportional
+    /// to the depth of the tree by descending through the octants containing `point`.
+    pub fn locate(&self, idx: usize, point: S::Vector) -> Option<&Octree<S>> {
+        let point = wrap(point, &self.options);
+        let mut node = self;
+        while !node.holds(idx) {
+            node = &node.children.as_ref()?[node.child_index(point)];
+        }
+        Some(node)
+    }
+
+    /// Whether the point with index `idx` is stored in this node itself.
+    fn holds(&self, idx: usize) -> bool {
+        self.points().any(|(i, ..)| i == idx)
+    }
+
+    /// Gravitational acceleration (with `G = 1`) at `point`, using the Barnes-Hut opening
+    /// criterion `s / d < theta` and softening length `softening` for the target, with the
+    /// kernel set in the options.
+    pub fn acceleration(&self, point: S::Vector, theta: S, softening: S) -> S::Vector {
+        self.field(point, theta, softening).0
+    }
+
+    /// Gravitational potential at `point`, see [`Octree::acceleration`].
+    pub fn potential(&self, point: S::Vector, theta: S, softening: S) -> S {
+        self.field(point, theta, softening).1
+    }
+
+    /// Acceleration and potential at `point` from a single tree walk.
+    pub fn field(&self, point: S::Vector, theta: S, softening: S) -> (S::Vector, S) {
+        let mut field = (S::Vector::zero(), S::ZERO);
+
+        self.walk(point, theta * theta, &mut |interaction| {
+            let (acc, pot) = match interaction {
+                Interaction::Cell(node) => node.cell_field(point, softening),
+                Interaction::Bucket(node) => node.bucket_field(point, softening),
+            };
+            field.0 += acc;
+            field.1 += pot;
+        });
+
+        field
+    }
+
+    /// Accelerations at each of `points`, see [`Octree::acceleration`].
+    ///
+    /// The points are split into cells of Morton order holding at most 256 points, evaluated in
+    /// parallel. The points of a cell share one walk of the tree, building an interaction list
+    /// for their bounding box as [`Octree::group_fields`] does for a node, so every point is at
+    /// least as accurate as with its own walk. With periodic boundaries, each point walks the
+    /// tree on its own.
+    pub fn accelerations(&self, points: &[S::Vector], theta: S, softening: S) -> Vec<S::Vector> {
+        self.fields(points, theta, softening)
+            .into_iter()
+            .map(|(acc, _)| acc)
+            .collect()
+    }
+
+    /// Potentials at each of `points`, see [`Octree::potential`] and [`Octree::accelerations`].
+    pub fn potentials(&self, points: &[S::Vector], theta: S, softening: S) -> Vec<S> {
+        self.fields(points, theta, softening)
+            .into_iter()
+            .map(|(_, pot)| pot)
+            .collect()
+    }
+
+    /// Accelerations and potentials at each of `points`, see [`Octree::accelerations`].
+    pub fn fields(&self, points: &[S::Vector], theta: S, softening: S) -> Vec<(S::Vector, S)> {
+        if self.options.periodic.is_some() {
+            return map_morton_par::<S, _>(points, self.center, self.extent, |point| {
+                self.field(point, theta, softening)
+            });
+        }
+
+        map_morton_chunks_par::<S, _>(points, self.center, self.extent, |chunk| {
+            self.shared_fields(chunk, theta, softening)
+        })
+    }
+
+    /// Visits every interaction needed to evaluate the field at `point`. Nodes passing the
+    /// opening criterion are visited as a [`Interaction::Cell`], otherwise the points stored in
+    /// the node are visited as an [`Interaction::Bucket`] and its children are opened.
+    pub(crate) fn walk<'a>(
+        &'a self,
+        point: S::Vector,
+        theta_sq: S,
+        visit: &mut impl FnMut(Interaction<'a, S>),
+    ) {
+        if self.positive.0 == S::ZERO && self.negative.0 == S::ZERO {
+            return;
+        }
+
+        let children = match self.children {
+            Some(ref children) => children,
+            None => return visit(Interaction::Bucket(self)),
+        };
+
+        let size = self.extent.component_max();
+        let dist_sq = image(self.com - point, &self.options).mag_sq();
+        if size * size < theta_sq * dist_sq && self.within_half_box(point) {
+            return visit(Interaction::Cell(self));
+        }
+
+        if self.count > 0 {
+            visit(Interaction::Bucket(self));
+        }
+        children
+            .iter()
+            .for_each(|child| child.walk(point, theta_sq, visit));
+    }
+
+    /// Whether the nearest image of this node's box lies within half of the periodic box of
+    /// `point` along every axis, so all of its points are their own nearest images. Always true
+    /// without periodic boundaries.
+    fn within_half_box(&self, point: S::Vector) -> bool {
+        let box_size = match self.options.periodic {
+            Some(box_size) => box_size,
+            None => return true,
+        };
+
+        let disp = image(self.center - point, &self.options).abs();
+        (0..3).all(|axis| {
+            disp[axis] + self.extent[axis] / S::from_f64(2.0) < box_size / S::from_f64(2.0)
+        })
+    }
+
+    /// Field at `point` from this node as a whole, expanded about its centre of mass.
+    pub(crate) fn cell_field(&self, point: S::Vector, softening: S) -> (S::Vector, S) {
+        let d = image(self.com - point, &self.options);
+        let (acc, pot) = cell_field(
+            self.options.multipole,
+            self.total_mass,
+            point + d,
+            &self.quadrupole,
+            point,
+            self.softening.max(softening),
+            self.options.softening,
+        );
+
+        match self.options.periodic {
+            Some(box_size) => {
+                let (corr_acc, corr_pot) = ewald::correction(d, box_size);
+                (
+                    acc + corr_acc * self.total_mass,
+                    pot + corr_pot * self.total_mass,
+                )
+            }
+            None => (acc, pot),
+        }
+    }
+
+    /// Field at `point` from the points stored in this node itself, summed exactly.
+    pub(crate) fn bucket_field(&self, point: S::Vector, softening: S) -> (S::Vector, S) {
+        let field = self.lanes().fold(
+            (S::Vector::zero(), S::ZERO),
+            |acc, &(_, positions, masses, softenings)| {
+                let (a, p) = lane_field(
+                    image_lane(positions, point, &self.options),
+                    masses,
+                    softenings,
+                    point,
+                    softening,
+                    &Gravity(self.options.softening),
+                );
+                (acc.0 + a, acc.1 + p)
+            },
+        );
+
+        let box_size = match self.options.periodic {
+            Some(box_size) => box_size,
+            None => return field,
+        };
+
+        // Points at zero distance, like the target itself, are skipped as in `lane_field`.
+        self.points()
+            .map(|(_, source, mass, _)| (image(source - point, &self.options), mass))
+            .filter(|&(d, _)| d != S::Vector::zero())
+            .fold(field, |(acc, pot), (d, mass)| {
+                let (corr_acc, corr_pot) = ewald::correction(d, box_size);
+                (acc + corr_acc * mass, pot + corr_pot * mass)
+            })
+    }
+}
+
+/// `point` wrapped into the periodic box of `options`, if any.
+fn wrap<S: Real>(point: S::Vector, options: &OctreeOptions<S>) -> S::Vector {
+    match options.periodic {
+        Some(box_size) => {
+            let wrap = |x: S| x - box_size * (x / box_size).floor();
+            S::Vector::new(wrap(point[0]), wrap(point[1]), wrap(point[2]))
+        }
+        None => point,
+    }
+}
+
+/// `points` wrapped into the periodic box of `options`, borrowed unchanged without one.
+fn wrap_all<'a, S: Real>(
+    points: &'a [S::Vector],
+    options: &OctreeOptions<S>,
+) -> Cow<'a, [S::Vector]> {
+    match options.periodic {
+        Some(_) => points.iter().map(|&point| wrap(point, options)).collect(),
+        None => Cow::Borrowed(points),
+    }
+}
+
+/// Nearest periodic image of the displacement `d` under `options`, or `d` itself without
+/// periodic boundaries.
+fn image<S: Real>(d: S::Vector, options: &OctreeOptions<S>) -> S::Vector {
+    match options.periodic {
+        Some(box_size) => {
+            let image = |x: S| x - box_size * (x / box_size).round();
+            S::Vector::new(image(d[0]), image(d[1]), image(d[2]))
+        }
+        None => d,
+    }
+}
+
+/// Lanes of `positions` moved to the nearest periodic images of their points from `point`, see
+/// [`image`].
+fn image_lane<S: Real>(
+    positions: S::WideVector,
+    point: S::Vector,
+    options: &OctreeOptions<S>,
+) -> S::WideVector {
+    let box_size = match options.periodic {
+        Some(box_size) => S::Wide::splat(box_size),
+        None => return positions,
+    };
+
+    let d = positions - S::WideVector::splat(point);
+    let image = |x: S::Wide| x - box_size * (x / box_size).round();
+    S::WideVector::splat(point) + S::WideVector::new(image(d.x()), image(d.y()), image(d.z()))
 }
 
-impl Default for Octree {
+/// Centre and extent of the root cell of `points`, which must not be empty, under `options`.
+/// Axes along which the points are flat get the largest extent of the other axes, or 1 if all
+/// points coincide.
+pub(crate) fn bounds<S: Real>(
+    points: &[S::Vector],
+    options: &OctreeOptions<S>,
+) -> (S::Vector, S::Vector) {
+    let min_bound = points
+        .iter()
+        .copied()
+        .reduce(S::Vector::min_by_component)
+        .unwrap();
+    let max_bound = points
+        .iter()
+        .copied()
+        .reduce(S::Vector::max_by_component)
+        .unwrap();
+
+    let center = (min_bound + max_bound) / S::from_f64(2.0);
+    let mut extent = max_bound - min_bound;
+
+    // Flat axes, or all points coinciding, would otherwise give cells of zero size.
+    let size = match extent.component_max() {
+        size if size > S::ZERO => size,
+        _ => S::ONE,
+    };
+    (0..3).for_each(|axis| {
+        if options.cubic || extent[axis] <= S::ZERO {
+            extent[axis] = size;
+        }
+    });
+
+    let padding = S::ONE + S::from_f64(2.0) * options.padding;
+    (center, extent * padding)
+}
+
+/// Maps `f` over `points` in parallel, calling it once per point in Morton order within the box
+/// at `center` with size `extent` for cache locality, see [`map_morton_chunks_par`].
+pub(crate) fn map_morton_par<S: Real, T: Copy + Default + Send>(
+    points: &[S::Vector],
+    center: S::Vector,
+    extent: S::Vector,
+    f: impl Fn(S::Vector) -> T + Sync,
+) -> Vec<T> {
+    map_morton_chunks_par::<S, _>(points, center, extent, |chunk| {
+        chunk.iter().map(|&point| f(point)).collect()
+    })
+}
+
+/// Maps `f` over `points` in parallel, calling it once per chunk of points and expecting one
+/// value per point back. The chunks are the cells of Morton order within the box at `center`
+/// with size `extent` holding at most [`FIELD_CHUNK`] points, so the points of a chunk are close
+/// together.
+pub(crate) fn map_morton_chunks_par<S: Real, T: Copy + Default + Send>(
+    points: &[S::Vector],
+    center: S::Vector,
+    extent: S::Vector,
+    f: impl Fn(&[S::Vector]) -> Vec<T> + Sync,
+) -> Vec<T> {
+    let mut order: Vec<(u64, usize)> = points
+        .iter()
+        .enumerate()
+        .map(|(i, &point)| (morton_key::<S>(point, center, extent), i))
+        .collect();
+    order.par_sort_unstable();
+
+    let mut chunks = Vec::new();
+    morton_chunks(&order, 0, &mut chunks);
+    let sorted: Vec<T> = chunks
+        .par_iter()
+        .flat_map_iter(|chunk| {
+            let chunk: Vec<S::Vector> = chunk.iter().map(|&(_, i)| points[i]).collect();
+            let values = f(&chunk);
+            debug_assert_eq!(values.len(), chunk.len());
+            values
+        })
+        .collect();
+
+    let mut values = vec![T::default(); points.len()];
+    order
+        .iter()
+        .zip(sorted)
+        .for_each(|(&(_, i), value)| values[i] = value);
+    values
+}
+
+/// Splits `order`, sorted Morton keys of the cell at `level`, into the deepest cells holding at
+/// most [`FIELD_CHUNK`] points, appending them to `chunks` in order.
+fn morton_chunks<'a>(order: &'a [(u64, usize)], level: u32, chunks: &mut Vec<&'a [(u64, usize)]>) {
+    if order.len() <= FIELD_CHUNK || level == MORTON_BITS {
+        chunks.push(order);
+        return;
+    }
+
+    let shift = 3 * (MORTON_BITS - 1 - level);
+    let mut rest = order;
+    while let Some(&(key, _)) = rest.first() {
+        let octant = (key >> shift) & 7;
+        let (cell, tail) =
+            rest.split_at(rest.partition_point(|&(k, _)| (k >> shift) & 7 == octant));
+        morton_chunks(cell, level + 1, chunks);
+        rest = tail;
+    }
+}
+
+/// Collects `(idx, value)` pairs into a vector of length `len` indexed by `idx`. Indices without
+/// a value, e.g. of removed points, get `T::default()`.
+pub(crate) fn scatter<T: Copy + Default>(values: Vec<(usize, T)>, len: usize) -> Vec<T> {
+    let mut scattered = vec![T::default(); len];
+    values
+        .into_iter()
+        .for_each(|(idx, value)| scattered[idx] = value);
+    scattered
+}
+
+/// Bits per axis of a [`morton_key`].
+pub(crate) const MORTON_BITS: u32 = 21;
+
+/// 63-bit Morton key of `point` within the box at `center` with size `extent`, interleaving the
+/// bits of the axes as `x y z` from the most significant end to match the child order of an
+/// [`Octree`]. Points outside the box are clamped onto it.
+pub(crate) fn morton_key<S: Real>(point: S::Vector, center: S::Vector, extent: S::Vector) -> u64 {
+    let cells = (1u64 << MORTON_BITS) as f64;
+    let quantize = |axis: usize| {
+        let u = ((point[axis] - center[axis]) / extent[axis]).to_f64() + 0.5;
+        spread_bits((u * cells).clamp(0., cells - 1.) as u64)
+    };
+
+    quantize(0) << 2 | quantize(1) << 1 | quantize(2)
+}
+
+/// Spreads the low [`MORTON_BITS`] bits of `x` so that two zero bits follow each of them.
+fn spread_bits(x: u64) -> u64 {
+    let mut x = x & ((1 << MORTON_BITS) - 1);
+    x = (x | x << 32) & 0x1f00000000ffff;
+    x = (x | x << 16) & 0x1f0000ff0000ff;
+    x = (x | x << 8) & 0x100f00f00f00f00f;
+    x = (x | x << 4) & 0x10c30c30c30c30c3;
+    (x | x << 2) & 0x1249249249249249
+}
+
+pub(crate) enum Interaction<'a, S: Real> {
+    /// A node accepted as a whole, approximated by its total mass at its centre of mass.
+    Cell(&'a Octree<S>),
+    /// The points stored directly in a node, summed exactly.
+    Bucket(&'a Octree<S>),
+}
+
+impl<S: Real> Default for Octree<S> {
     fn default() -> Self {
         Self {
-            point: ([0; 8], Vec3x8::zero(), f32x8::ZERO),
+            point: Default::default(),
+            overflow: Vec::new(),
             count: 0,
-            total_mass: 0.0,
-            com: Vec3::zero(),
+            total_mass: S::ZERO,
+            com: S::Vector::zero(),
+            positive: (S::ZERO, S::Vector::zero()),
+            negative: (S::ZERO, S::Vector::zero()),
+            quadrupole: Quadrupole::default(),
+            softening: S::ZERO,
             children: None,
-            center: Vec3::zero(),
-            extent: Vec3::zero(),
+            center: S::Vector::zero(),
+            extent: S::Vector::zero(),
+            depth: 0,
+            options: OctreeOptions::default(),
+            index_count: 0,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Octree;
-    use ultraviolet::Vec3;
+    use super::{morton_key, Octree, OctreeOptions};
+    use crate::{
+        direct,
+        initial_conditions::unit_cube,
+        multipole::Multipole,
+        test_helpers::{errors, walk_fields},
+    };
+    use rand::prelude::*;
+    use ultraviolet::{DVec3, Vec3};
 
     #[test]
     fn test_octree_correctness() {
@@ -186,13 +1192,14 @@ mod tests {
             Vec3::new(1.0, 1.0, -1.0),
             Vec3::new(1.0, 1.0, 1.0),
         ];
-        let masses = vec![1.; 8];
+        let masses = vec![1f32; 8];
 
         let oct = Octree::construct(&points, &masses);
         println!("{:#?}", oct);
 
         assert_eq!(oct.total_mass, 8.);
         assert_eq!(oct.com, Vec3::zero());
+        oct.validate().unwrap();
 
         (0..8).for_each(|i| {
             let found = oct.find(i).expect("Could not find element");
@@ -222,4 +1229,458 @@ mod tests {
             );
         });
     }
+
+    #[test]
+    fn test_field_matches_direct_sum() {
+        let points: Vec<Vec3> = (0..64)
+            .map(|i| {
+                let t = i as f32;
+                Vec3::new((t * 0.37).sin(), (t * 0.71).cos(), (t * 0.13).sin() * 2.)
+            })
+            .collect();
+        let masses: Vec<f32> = (0..64).map(|i| 1. + (i % 3) as f32).collect();
+
+        let oct = Octree::construct(&points, &masses);
+        let softening = 0.05;
+
+        points.iter().enumerate().for_each(|(i, &p)| {
+            let expected = points
+                .iter()
+                .zip(&masses)
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .fold(Vec3::zero(), |acc, (_, (&q, &m))| {
+                    let diff = q - p;
+                    let r_sq = diff.mag_sq() + softening * softening;
+                    acc + diff * (m / (r_sq * r_sq.sqrt()))
+                });
+
+            let expected_potential = points
+                .iter()
+                .zip(&masses)
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, (&q, &m))| -m / ((q - p).mag_sq() + softening * softening).sqrt())
+                .sum::<f32>();
+
+            let (exact, exact_potential) = oct.field(p, 0., softening);
+            assert!(
+                (exact_potential - expected_potential).abs() <= 1e-4 * expected_potential.abs()
+            );
+
+            assert!(
+                (exact - expected).mag() <= 1e-4 * expected.mag(),
+                "{:?} != {:?}",
+                exact,
+                expected
+            );
+
+            let approx = oct.acceleration(p, 0.5, softening);
+            assert!((approx - expected).mag() <= 5e-2 * expected.mag());
+
+            let approx_potential = oct.potential(p, 0.5, softening);
+            assert!(
+                (approx_potential - expected_potential).abs() <= 1e-2 * expected_potential.abs()
+            );
+        });
+    }
+
+    #[test]
+    fn test_f64_field_matches_direct_sum() {
+        let points: Vec<DVec3> = (0..64)
+            .map(|i| {
+                let t = i as f64;
+                DVec3::new((t * 0.37).sin(), (t * 0.71).cos(), (t * 0.13).sin() * 2.)
+            })
+            .collect();
+        let masses: Vec<f64> = (0..64).map(|i| 1. + (i % 3) as f64).collect();
+
+        let oct = Octree::construct(&points, &masses);
+        let expected = direct::fields(&points, &masses, 0.05);
+
+        points.iter().zip(expected).for_each(|(&p, (acc, pot))| {
+            let (exact, exact_potential) = oct.field(p, 0., 0.05);
+            assert!((exact - acc).mag() <= 1e-12 * acc.mag());
+            assert!((exact_potential - pot).abs() <= 1e-12 * pot.abs());
+        });
+    }
+
+    fn max_depth(tree: &Octree) -> u32 {
+        tree.children
+            .iter()
+            .flat_map(|children| children.iter())
+            .map(max_depth)
+            .fold(tree.depth, u32::max)
+    }
+
+    #[test]
+    fn test_coincident_points() {
+        let n = 5000;
+        let point = Vec3::new(0.5, -2., 3.);
+        let oct = Octree::construct(&vec![point; n], &vec![1f32; n]);
+
+        assert!(max_depth(&oct) <= OctreeOptions::<f32>::default().max_depth);
+        assert_eq!(oct.total_mass, n as f32);
+        assert!((oct.com - point).mag() < 1e-5);
+        assert!(oct.extent.component_min() > 0.);
+        (0..n)
+            .step_by(499)
+            .for_each(|i| assert!(oct.find(i).is_some()));
+
+        // Coincident points exert no force on each other, and act as one mass from afar.
+        let (acc, pot) = oct.field(point, 0.5, 0.);
+        assert_eq!((acc, pot), (Vec3::zero(), 0.));
+
+        let acc = oct.acceleration(point + Vec3::new(10., 0., 0.), 0.5, 0.);
+        assert!((acc - Vec3::new(-(n as f32) / 100., 0., 0.)).mag() < 1e-3 * acc.mag());
+    }
+
+    #[test]
+    fn test_near_coincident_points() {
+        let n = 2000;
+        let points: Vec<Vec3> = (0..n)
+            .map(|i| Vec3::new(1., 1., 1.) + Vec3::broadcast(1e-7 * (i % 3) as f32))
+            .chain([Vec3::zero()])
+            .collect();
+        let masses = vec![1f32; points.len()];
+
+        let options = OctreeOptions {
+            max_depth: 64,
+            min_size: 1e-3,
+            ..Default::default()
+        };
+        let oct = Octree::construct_with(&points, &masses, options);
+
+        assert!(max_depth(&oct) <= 11, "{}", max_depth(&oct));
+        assert_eq!(oct.total_mass, points.len() as f32);
+        assert!(oct.find(n).is_some() && oct.find(n - 1).is_some());
+    }
+
+    fn assert_same_tree(a: &Octree, b: &Octree) {
+        assert_eq!((a.count, a.depth), (b.count, b.depth));
+        assert_eq!((a.center, a.extent), (b.center, b.extent));
+        assert_eq!((a.com, a.total_mass), (b.com, b.total_mass));
+        a.lanes()
+            .zip(b.lanes())
+            .for_each(|(a, b)| assert_eq!(a.0, b.0));
+
+        match (&a.children, &b.children) {
+            (Some(a), Some(b)) => a
+                .iter()
+                .zip(b.iter())
+                .for_each(|(a, b)| assert_same_tree(a, b)),
+            (None, None) => {}
+            _ => panic!("Children differ at depth {}", a.depth),
+        }
+    }
+
+    #[test]
+    fn test_parallel_construction() {
+        let mut rng = StdRng::seed_from_u64(13);
+        let points: Vec<Vec3> = unit_cube::<f32>(20000, &mut rng)
+            .into_iter()
+            .chain(std::iter::repeat_n(Vec3::broadcast(0.25), 5000))
+            .collect();
+        let masses: Vec<f32> = points.iter().map(|_| rng.gen()).collect();
+
+        let serial = Octree::construct(&points, &masses);
+        let parallel = Octree::construct_par(&points, &masses);
+        assert_same_tree(&serial, &parallel);
+
+        let empty = Octree::<f32>::construct_par(&[], &[]);
+        assert_eq!(empty.total_mass, 0.);
+    }
+
+    #[test]
+    fn test_batch_fields_beat_single_walks() {
+        let mut rng = StdRng::seed_from_u64(14);
+        let points = unit_cube::<f32>(3000, &mut rng);
+        let masses = vec![1f32; points.len()];
+        let oct = Octree::construct(&points, &masses);
+
+        // Includes targets outside the root cell.
+        let targets: Vec<Vec3> = points
+            .iter()
+            .map(|&p| p * 1.5 - Vec3::broadcast(0.25))
+            .collect();
+
+        let fields = oct.fields(&targets, 0.5, 0.01);
+        let accelerations = oct.accelerations(&targets, 0.5, 0.01);
+        let potentials = oct.potentials(&targets, 0.5, 0.01);
+        assert_eq!(fields.len(), targets.len());
+        fields.iter().enumerate().for_each(|(i, &field)| {
+            assert_eq!((accelerations[i], potentials[i]), field);
+        });
+
+        // Shared interaction lists are at least as strict as the opening criterion of each
+        // target's own walk.
+        let exact = walk_fields(&oct, &targets, 0., 0.01);
+        let (shared_acc, shared_pot) = errors::<f32>(&exact, &fields);
+        let (single_acc, single_pot) =
+            errors::<f32>(&exact, &walk_fields(&oct, &targets, 0.5, 0.01));
+        assert!(shared_acc <= single_acc && shared_pot <= single_pot);
+        assert!(shared_acc < 1e-2);
+    }
+
+    #[test]
+    fn test_morton_key_follows_child_order() {
+        let (center, extent) = (Vec3::zero(), Vec3::broadcast(2.));
+        let key = |p: Vec3| morton_key::<f32>(p, center, extent);
+
+        // The top three bits select the child, as in `Octree::get_child`.
+        (0..8).for_each(|i| {
+            let sign = |bit: usize| if i & bit != 0 { 0.5 } else { -0.5 };
+            assert_eq!(key(Vec3::new(sign(4), sign(2), sign(1))) >> 60, i as u64);
+        });
+
+        assert_eq!(key(Vec3::broadcast(-5.)), 0);
+        assert_eq!(key(Vec3::broadcast(5.)), (1 << 63) - 1);
+    }
+
+    #[test]
+    fn test_cubic_root_validates() {
+        let mut rng = StdRng::seed_from_u64(16);
+        // A flattened distribution, whose tight bounding box is far from cubic.
+        let points: Vec<Vec3> = (0..20000)
+            .map(|_| Vec3::new(rng.gen(), rng.gen::<f32>() * 0.1, rng.gen::<f32>() * 0.01))
+            .collect();
+        let masses: Vec<f32> = points.iter().map(|_| rng.gen()).collect();
+
+        let options = OctreeOptions {
+            cubic: true,
+            padding: 1e-3,
+            ..Default::default()
+        };
+        let mut oct = Octree::construct_par_with(&points, &masses, options);
+
+        assert_eq!(oct.extent, Vec3::broadcast(oct.extent.x));
+        assert!(oct.extent.x > 1.);
+        oct.validate().unwrap();
+
+        let child = &oct.children.as_ref().unwrap()[0];
+        assert_eq!(child.extent, oct.extent / 2.);
+
+        oct.children.as_mut().unwrap()[0].total_mass += 1.;
+        assert!(oct.validate().is_err());
+    }
+
+    /// Depth and sorted indices of the points of every node, in depth first order.
+    fn structure(tree: &Octree) -> Vec<(u32, Vec<usize>)> {
+        let mut indices: Vec<usize> = tree.points().map(|(idx, ..)| idx).collect();
+        indices.sort_unstable();
+
+        std::iter::once((tree.depth, indices))
+            .chain(
+                tree.children
+                    .iter()
+                    .flat_map(|children| children.iter())
+                    .flat_map(structure),
+            )
+            .collect()
+    }
+
+    #[test]
+    fn test_leaf_only_buckets() {
+        let mut rng = StdRng::seed_from_u64(17);
+        let points = unit_cube::<f32>(3000, &mut rng);
+        let masses = vec![1f32; points.len()];
+
+        let options = OctreeOptions {
+            bucket_lanes: 4,
+            leaf_only: true,
+            cubic: true,
+            padding: 1e-3,
+            ..Default::default()
+        };
+        let oct = Octree::construct_with(&points, &masses, options);
+        oct.validate().unwrap();
+
+        let nodes = structure(&oct);
+        assert!(nodes.iter().all(|(_, indices)| indices.len() <= 32));
+        assert_eq!(
+            nodes
+                .iter()
+                .map(|(_, indices)| indices.len())
+                .sum::<usize>(),
+            points.len()
+        );
+
+        fn assert_leaf_only(tree: &Octree) {
+            if let Some(children) = &tree.children {
+                assert_eq!(tree.count, 0);
+                children.iter().for_each(assert_leaf_only);
+            }
+        }
+        assert_leaf_only(&oct);
+
+        // The same points inserted in reverse give the same tree.
+        let n = points.len();
+        let reversed: Vec<Vec3> = points.iter().rev().copied().collect();
+        let reversed = Octree::construct_with(&reversed, &masses, options);
+        let reversed: Vec<_> = structure(&reversed)
+            .into_iter()
+            .map(|(depth, indices)| {
+                let mut indices: Vec<usize> = indices.iter().map(|i| n - 1 - i).collect();
+                indices.sort_unstable();
+                (depth, indices)
+            })
+            .collect();
+        assert_eq!(reversed, nodes);
+
+        assert_same_tree(&oct, &Octree::construct_par_with(&points, &masses, options));
+
+        let exact = direct::accelerations(&points, &masses, 1e-2);
+        let approx = oct.accelerations(&points, 0., 1e-2);
+        exact
+            .iter()
+            .zip(&approx)
+            .for_each(|(&exact, &approx)| assert!((exact - approx).mag() <= 1e-3 * exact.mag()));
+    }
+
+    #[test]
+    fn test_find_and_locate() {
+        let mut rng = StdRng::seed_from_u64(18);
+        let points: Vec<Vec3> = unit_cube::<f32>(2000, &mut rng)
+            .into_iter()
+            .chain(std::iter::repeat_n(Vec3::broadcast(0.5), 100))
+            .collect();
+        let masses = vec![1f32; points.len()];
+
+        for leaf_only in [false, true] {
+            let options = OctreeOptions {
+                leaf_only,
+                ..Default::default()
+            };
+            let oct = Octree::construct_with(&points, &masses, options);
+
+            (0..points.len()).for_each(|i| {
+                let found = oct.find(i).expect("Could not find element");
+                let located = oct.locate(i, points[i]).expect("Could not locate element");
+                assert!(std::ptr::eq(found, located));
+                assert!(found
+                    .points()
+                    .any(|(idx, point, ..)| idx == i && point == points[i]));
+            });
+
+            // Unused slots hold index 0, which must not match nodes without point 0.
+            let found = oct.find(0).unwrap();
+            assert_eq!(found.points().filter(|&(idx, ..)| idx == 0).count(), 1);
+
+            assert!(oct.find(points.len()).is_none());
+            assert!(oct.locate(points.len(), points[0]).is_none());
+        }
+    }
+
+    #[test]
+    fn test_refit_matches_fresh_build() {
+        let mut rng = StdRng::seed_from_u64(19);
+        let points = unit_cube::<f32>(1500, &mut rng);
+        let masses: Vec<f32> = points.iter().map(|_| rng.gen()).collect();
+        // Most points move slightly, some cross several cells, none leave the root.
+        let moved: Vec<Vec3> = points
+            .iter()
+            .map(|&p| {
+                let step = if rng.gen::<f32>() < 0.1 { 0.2 } else { 1e-3 };
+                let disp = Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 2. - Vec3::one();
+                (p + disp * step).clamped(Vec3::zero(), Vec3::one())
+            })
+            .collect();
+
+        for leaf_only in [false, true] {
+            let options = OctreeOptions {
+                leaf_only,
+                multipole: Multipole::Quadrupole,
+                cubic: true,
+                padding: 1e-3,
+                ..Default::default()
+            };
+            let mut refit = Octree::construct_with(&points, &masses, options);
+            refit.update_positions(&moved);
+            refit.validate().unwrap();
+
+            // A fresh build in the same root cell.
+            let mut fresh = Octree {
+                center: refit.center,
+                extent: refit.extent,
+                options,
+                ..Default::default()
+            };
+            (0..moved.len()).for_each(|i| fresh.add_point(i, moved[i], masses[i]));
+            fresh.compute();
+
+            if leaf_only {
+                assert_eq!(structure(&refit), structure(&fresh));
+            }
+            assert!((refit.total_mass - fresh.total_mass).abs() < 1e-3);
+            assert!((refit.com - fresh.com).mag() < 1e-5);
+            assert!((refit.quadrupole.xy - fresh.quadrupole.xy).abs() < 1e-2);
+
+            let exact = refit.accelerations(&moved, 0., 1e-2);
+            let approx = fresh.accelerations(&moved, 0., 1e-2);
+            exact
+                .iter()
+                .zip(&approx)
+                .for_each(|(&a, &b)| assert!((a - b).mag() <= 1e-3 * a.mag()));
+        }
+    }
+
+    #[test]
+    fn test_refit_rebuilds_when_leaving_root() {
+        let points: Vec<Vec3> = (0..100).map(|i| Vec3::broadcast(i as f32 / 100.)).collect();
+        let masses = vec![1f32; points.len()];
+        let options = OctreeOptions {
+            padding: 1e-3,
+            ..Default::default()
+        };
+        let mut oct = Octree::construct_with(&points, &masses, options);
+
+        let mut moved = points.clone();
+        moved[42] = Vec3::new(-10., 5., 0.);
+        oct.update_positions(&moved);
+
+        oct.validate().unwrap();
+        assert!(oct.extent.x > 10.);
+        assert_eq!(oct.total_mass, 100.);
+        assert!(oct.locate(42, moved[42]).is_some());
+    }
+
+    #[test]
+    fn test_remove_point() {
+        let mut rng = StdRng::seed_from_u64(190);
+        let points = unit_cube::<f32>(1000, &mut rng);
+        let masses = vec![1f32; points.len()];
+
+        for leaf_only in [false, true] {
+            let options = OctreeOptions {
+                leaf_only,
+                padding: 1e-3,
+                ..Default::default()
+            };
+            let mut oct = Octree::construct_with(&points, &masses, options);
+
+            (0..points.len()).step_by(2).for_each(|i| {
+                assert_eq!(oct.remove_point(i, points[i]), Some(1.));
+                assert_eq!(oct.remove_point(i, points[i]), None);
+            });
+            oct.compute();
+            oct.validate().unwrap();
+
+            assert_eq!(oct.total_mass, 500.);
+            assert!(oct.find(0).is_none() && oct.find(1).is_some());
+
+            let com = (1..points.len())
+                .step_by(2)
+                .map(|i| points[i])
+                .sum::<Vec3>()
+                / 500.;
+            assert!((oct.com - com).mag() < 1e-5);
+
+            (1..points.len())
+                .step_by(2)
+                .for_each(|i| assert!(oct.remove_point(i, points[i]).is_some()));
+            oct.compute();
+            assert!(oct.children.is_none());
+            assert_eq!((oct.count, oct.total_mass), (0, 0.));
+        }
+    }
 }
@@ -0,0 +1,359 @@
+use std::{
+    fmt::Debug,
+    iter::Sum,
+    ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign},
+};
+
+use ultraviolet::{f32x8, f64x4, DVec3, DVec3x4, Vec3, Vec3x8};
+use wide::{CmpEq, CmpLt};
+
+/// Floating point precision of an [`Octree`](crate::octtree::Octree), pairing the scalar type
+/// with the `ultraviolet` vector and SIMD types of the same precision: `Vec3` with `Vec3x8` for
+/// `f32`, and `DVec3` with `DVec3x4` for `f64`.
+pub trait Real:
+    Copy
+    + Debug
+    + Default
+    + PartialOrd
+    + Send
+    + Sync
+    + 'static
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+    + Sum
+{
+    /// Number of lanes in [`Real::Wide`].
+    const LANES: usize;
+    const ZERO: Self;
+    const ONE: Self;
+
+    type Vector: Vector<Self>;
+    type Wide: Wide<Self>;
+    type WideVector: WideVector<Self>;
+    /// `[Self; LANES]`.
+    type Array: Copy + Debug + Default + Send + Sync + AsRef<[Self]> + AsMut<[Self]>;
+    /// `[usize; LANES]`, e.g. the indices of the points in a SIMD lane.
+    type Indices: Copy + Debug + Default + Send + Sync + AsRef<[usize]> + AsMut<[usize]>;
+
+    fn from_f64(x: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn floor(self) -> Self;
+    fn round(self) -> Self;
+    fn exp(self) -> Self;
+    fn is_sign_positive(self) -> bool;
+}
+
+/// Three dimensional vector of [`Real`]s.
+pub trait Vector<S>:
+    Copy
+    + Debug
+    + Default
+    + PartialEq
+    + Send
+    + Sync
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Mul<S, Output = Self>
+    + Div<S, Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign<S>
+    + DivAssign<S>
+    + Index<usize, Output = S>
+    + IndexMut<usize>
+    + Sum
+{
+    fn new(x: S, y: S, z: S) -> Self;
+    fn zero() -> Self;
+    fn broadcast(s: S) -> Self;
+    fn dot(&self, other: Self) -> S;
+    fn mag_sq(&self) -> S;
+    fn mag(&self) -> S;
+    fn min_by_component(self, other: Self) -> Self;
+    fn max_by_component(self, other: Self) -> Self;
+    fn component_max(&self) -> S;
+    fn component_min(&self) -> S;
+    fn abs(&self) -> Self;
+}
+
+/// SIMD vector of [`Real::LANES`] scalars.
+pub trait Wide<S: Real>:
+    Copy
+    + Debug
+    + Default
+    + Send
+    + Sync
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    fn new(array: S::Array) -> Self;
+    fn splat(s: S) -> Self;
+    fn to_array(self) -> S::Array;
+    fn sqrt(self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn round(self) -> Self;
+    fn reduce_add(self) -> S;
+    /// Lanewise equality as an all-ones or all-zeros mask.
+    fn cmp_eq(self, other: Self) -> Self;
+    /// Lanewise less than as an all-ones or all-zeros mask.
+    fn cmp_lt(self, other: Self) -> Self;
+    /// Picks lanes of `t` where `self` is an all-ones mask, and of `f` otherwise.
+    fn blend(self, t: Self, f: Self) -> Self;
+}
+
+/// SIMD vector of [`Real::LANES`] three dimensional vectors.
+pub trait WideVector<S: Real>:
+    Copy
+    + Debug
+    + Default
+    + Send
+    + Sync
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<S::Wide, Output = Self>
+    + Neg<Output = Self>
+{
+    fn new(x: S::Wide, y: S::Wide, z: S::Wide) -> Self;
+    fn splat(v: S::Vector) -> Self;
+    fn x(&self) -> S::Wide;
+    fn y(&self) -> S::Wide;
+    fn z(&self) -> S::Wide;
+    fn mag_sq(&self) -> S::Wide;
+    /// Sum of all lanes.
+    fn reduce_add(self) -> S::Vector;
+}
+
+macro_rules! impl_real {
+    ($t:ident, $lanes:expr, $v:ident, $w:ident, $wv:ident) => {
+        impl Real for $t {
+            const LANES: usize = $lanes;
+            const ZERO: Self = 0.;
+            const ONE: Self = 1.;
+
+            type Vector = $v;
+            type Wide = $w;
+            type WideVector = $wv;
+            type Array = [$t; $lanes];
+            type Indices = [usize; $lanes];
+
+            #[inline]
+            fn from_f64(x: f64) -> Self {
+                x as $t
+            }
+
+            #[inline]
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            #[inline]
+            fn sqrt(self) -> Self {
+                $t::sqrt(self)
+            }
+
+            #[inline]
+            fn abs(self) -> Self {
+                $t::abs(self)
+            }
+
+            #[inline]
+            fn max(self, other: Self) -> Self {
+                $t::max(self, other)
+            }
+
+            #[inline]
+            fn min(self, other: Self) -> Self {
+                $t::min(self, other)
+            }
+
+            #[inline]
+            fn floor(self) -> Self {
+                $t::floor(self)
+            }
+
+            #[inline]
+            fn round(self) -> Self {
+                $t::round(self)
+            }
+
+            #[inline]
+            fn exp(self) -> Self {
+                $t::exp(self)
+            }
+
+            #[inline]
+            fn is_sign_positive(self) -> bool {
+                $t::is_sign_positive(self)
+            }
+        }
+
+        impl Vector<$t> for $v {
+            #[inline]
+            fn new(x: $t, y: $t, z: $t) -> Self {
+                $v::new(x, y, z)
+            }
+
+            #[inline]
+            fn zero() -> Self {
+                $v::zero()
+            }
+
+            #[inline]
+            fn broadcast(s: $t) -> Self {
+                $v::broadcast(s)
+            }
+
+            #[inline]
+            fn dot(&self, other: Self) -> $t {
+                $v::dot(self, other)
+            }
+
+            #[inline]
+            fn mag_sq(&self) -> $t {
+                $v::mag_sq(self)
+            }
+
+            #[inline]
+            fn mag(&self) -> $t {
+                $v::mag(self)
+            }
+
+            #[inline]
+            fn min_by_component(self, other: Self) -> Self {
+                $v::min_by_component(self, other)
+            }
+
+            #[inline]
+            fn max_by_component(self, other: Self) -> Self {
+                $v::max_by_component(self, other)
+            }
+
+            #[inline]
+            fn component_max(&self) -> $t {
+                $v::component_max(self)
+            }
+
+            #[inline]
+            fn component_min(&self) -> $t {
+                $v::component_min(self)
+            }
+
+            #[inline]
+            fn abs(&self) -> Self {
+                $v::abs(self)
+            }
+        }
+
+        impl Wide<$t> for $w {
+            #[inline]
+            fn new(array: [$t; $lanes]) -> Self {
+                $w::new(array)
+            }
+
+            #[inline]
+            fn splat(s: $t) -> Self {
+                $w::splat(s)
+            }
+
+            #[inline]
+            fn to_array(self) -> [$t; $lanes] {
+                $w::to_array(self)
+            }
+
+            #[inline]
+            fn sqrt(self) -> Self {
+                $w::sqrt(self)
+            }
+
+            #[inline]
+            fn max(self, other: Self) -> Self {
+                $w::max(self, other)
+            }
+
+            #[inline]
+            fn round(self) -> Self {
+                $w::round(self)
+            }
+
+            #[inline]
+            fn reduce_add(self) -> $t {
+                $w::reduce_add(self)
+            }
+
+            #[inline]
+            fn cmp_eq(self, other: Self) -> Self {
+                CmpEq::cmp_eq(self, other)
+            }
+
+            #[inline]
+            fn cmp_lt(self, other: Self) -> Self {
+                CmpLt::cmp_lt(self, other)
+            }
+
+            #[inline]
+            fn blend(self, t: Self, f: Self) -> Self {
+                $w::blend(self, t, f)
+            }
+        }
+
+        impl WideVector<$t> for $wv {
+            #[inline]
+            fn new(x: $w, y: $w, z: $w) -> Self {
+                $wv::new(x, y, z)
+            }
+
+            #[inline]
+            fn splat(v: $v) -> Self {
+                $wv::splat(v)
+            }
+
+            #[inline]
+            fn x(&self) -> $w {
+                self.x
+            }
+
+            #[inline]
+            fn y(&self) -> $w {
+                self.y
+            }
+
+            #[inline]
+            fn z(&self) -> $w {
+                self.z
+            }
+
+            #[inline]
+            fn mag_sq(&self) -> $w {
+                $wv::mag_sq(self)
+            }
+
+            #[inline]
+            fn reduce_add(self) -> $v {
+                $v::new(
+                    self.x.reduce_add(),
+                    self.y.reduce_add(),
+                    self.z.reduce_add(),
+                )
+            }
+        }
+    };
+}
+
+impl_real!(f32, 8, Vec3, f32x8, Vec3x8);
+impl_real!(f64, 4, DVec3, f64x4, DVec3x4);
@@ -0,0 +1,230 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use ultraviolet::Vec3;
+
+/// Vertical field of view of the perspective projection, in radians.
+pub const FOV_Y: f32 = std::f32::consts::FRAC_PI_3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+}
+
+/// Camera orbiting `target` at `distance`, oriented by `yaw` about the y axis and `pitch` above
+/// the xz plane.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub target: Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub projection: Projection,
+}
+
+impl Camera {
+    /// Camera looking down the z axis with `view_radius` visible above and below the target.
+    pub fn new(view_radius: f32) -> Self {
+        Self {
+            target: Vec3::zero(),
+            distance: view_radius / (FOV_Y / 2.).tan(),
+            yaw: 0.,
+            pitch: 0.,
+            projection: Projection::Perspective,
+        }
+    }
+
+    /// Rotates the camera about its target, keeping it from flipping over the poles.
+    pub fn orbit(&mut self, d_yaw: f32, d_pitch: f32) {
+        let max_pitch = std::f32::consts::FRAC_PI_2 - 1e-3;
+        self.yaw += d_yaw;
+        self.pitch = (self.pitch + d_pitch).clamp(-max_pitch, max_pitch);
+    }
+
+    /// Moves the target in the view plane by fractions of the distance to it.
+    pub fn pan(&mut self, right: f32, up: f32) {
+        let (right_dir, up_dir, _) = self.basis();
+        self.target += (right_dir * right + up_dir * up) * self.distance;
+    }
+
+    /// Scales the distance to the target by `factor`.
+    pub fn zoom(&mut self, factor: f32) {
+        self.distance *= factor;
+    }
+
+    pub fn toggle_projection(&mut self) {
+        self.projection = match self.projection {
+            Projection::Perspective => Projection::Orthographic,
+            Projection::Orthographic => Projection::Perspective,
+        };
+    }
+
+    /// Right, up and forward unit vectors of the view.
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+
+        let forward = -Vec3::new(cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw);
+        let right = forward.cross(Vec3::unit_y()).normalized();
+        let up = right.cross(forward);
+
+        (right, up, forward)
+    }
+
+    /// Projects `point` to normalized screen coordinates, where `[-1, 1]` spans the height of
+    /// the screen and y points up. Returns `None` for points behind a perspective camera.
+    pub fn project(&self, point: Vec3) -> Option<(f32, f32)> {
+        let (right, up, forward) = self.basis();
+        let eye = self.target - forward * self.distance;
+        let rel = point - eye;
+
+        let half_height = match self.projection {
+            Projection::Perspective => {
+                let depth = rel.dot(forward);
+                if depth <= 0. {
+                    return None;
+                }
+                depth * (FOV_Y / 2.).tan()
+            }
+            // Matches the perspective scale at the target so toggling keeps the framing.
+            Projection::Orthographic => self.distance * (FOV_Y / 2.).tan(),
+        };
+
+        Some((rel.dot(right) / half_height, rel.dot(up) / half_height))
+    }
+}
+
+/// CPU rasterizer splatting particles with additive brightness into an RGBA8 frame.
+#[derive(Clone, Debug)]
+pub struct Renderer {
+    width: u32,
+    height: u32,
+    /// Brightness added by every particle landing on a pixel.
+    pub intensity: f32,
+    brightness: Vec<f32>,
+    frame: Vec<u8>,
+}
+
+impl Renderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let pixels = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            intensity: 0.25,
+            brightness: vec![0.; pixels],
+            frame: vec![0; 4 * pixels],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The last drawn frame, row-major RGBA8 from the top left.
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Draws `positions` as seen from `camera`, returning the new frame.
+    pub fn draw(&mut self, positions: &[Vec3], camera: &Camera) -> &[u8] {
+        let (width, height) = (self.width as f32, self.height as f32);
+
+        self.brightness.iter_mut().for_each(|b| *b = 0.);
+        positions.iter().for_each(|&x| {
+            let (sx, sy) = match camera.project(x) {
+                Some(screen) => screen,
+                None => return,
+            };
+            let px = width / 2. + sx * height / 2.;
+            let py = height / 2. - sy * height / 2.;
+
+            if (0. ..width).contains(&px) && (0. ..height).contains(&py) {
+                self.brightness[py as usize * self.width as usize + px as usize] += self.intensity;
+            }
+        });
+
+        for (pixel, &b) in self.frame.chunks_exact_mut(4).zip(&self.brightness) {
+            // Saturate smoothly so dense regions do not clip to a flat white.
+            let value = 1. - (-b).exp();
+            pixel.copy_from_slice(&[
+                (value * 0xff as f32) as u8,
+                (value * 0xe0 as f32) as u8,
+                (value * 0xc0 as f32) as u8,
+                0xff,
+            ]);
+        }
+
+        &self.frame
+    }
+
+    /// Writes the frame as a binary PPM (P6), dropping the alpha channel.
+    pub fn write_ppm(&self, mut writer: impl Write) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        self.frame
+            .chunks_exact(4)
+            .try_for_each(|pixel| writer.write_all(&pixel[..3]))?;
+        writer.flush()
+    }
+
+    /// Writes the frame as an RGBA PNG.
+    pub fn write_png(&self, writer: impl Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.frame)?;
+        Ok(writer.finish()?)
+    }
+
+    /// Saves the frame to `path`, as a PPM if its extension is `ppm` and a PNG otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let writer = BufWriter::new(File::create(path)?);
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ppm") => self.write_ppm(writer),
+            _ => self.write_png(writer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Camera, Projection, Renderer};
+    use ultraviolet::Vec3;
+
+    #[test]
+    fn test_splat_and_ppm() {
+        let mut camera = Camera::new(1.);
+        let mut renderer = Renderer::new(4, 2);
+
+        for projection in [Projection::Perspective, Projection::Orthographic] {
+            camera.projection = projection;
+            let frame = renderer.draw(&[Vec3::zero(), Vec3::zero()], &camera);
+
+            // The target lands on the pixel just right of and below the centre.
+            let lit: Vec<usize> = frame
+                .chunks_exact(4)
+                .enumerate()
+                .filter(|(_, pixel)| pixel[0] > 0)
+                .map(|(i, _)| i)
+                .collect();
+            assert_eq!(lit, vec![6]);
+        }
+
+        let mut ppm = Vec::new();
+        renderer.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n4 2\n255\n"));
+        assert_eq!(ppm.len(), 11 + 4 * 2 * 3);
+    }
+}
@@ -0,0 +1,184 @@
+use ultraviolet::Vec3;
+
+use crate::{
+    forces::{ForceProvider, TreeForces},
+    integrator::{Integrator, VelocityVerlet},
+    timestep::BlockTimesteps,
+};
+
+/// Particle state in struct-of-arrays form.
+#[derive(Clone, Debug, Default)]
+pub struct Particles {
+    pub positions: Vec<Vec3>,
+    pub velocities: Vec<Vec3>,
+    pub masses: Vec<f32>,
+    pub accelerations: Vec<Vec3>,
+}
+
+impl Particles {
+    /// Creates the particle state, computing the initial accelerations with `forces`.
+    pub fn new(
+        positions: Vec<Vec3>,
+        velocities: Vec<Vec3>,
+        masses: Vec<f32>,
+        forces: &dyn ForceProvider,
+    ) -> Self {
+        assert_eq!(
+            positions.len(),
+            velocities.len(),
+            "Length of given positions not equal to length of given velocities"
+        );
+
+        let mut particles = Self {
+            accelerations: vec![Vec3::zero(); positions.len()],
+            positions,
+            velocities,
+            masses,
+        };
+        particles.compute_accelerations(forces);
+        particles
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Recomputes `accelerations` from the current positions.
+    pub fn compute_accelerations(&mut self, forces: &dyn ForceProvider) {
+        self.accelerations = forces.accelerations(&self.positions, &self.masses);
+    }
+
+    /// Advances velocities by `dt` using the current accelerations.
+    pub fn kick(&mut self, dt: f32) {
+        self.velocities
+            .iter_mut()
+            .zip(&self.accelerations)
+            .for_each(|(v, &a)| *v += a * dt);
+    }
+
+    /// Advances positions by `dt` using the current velocities.
+    pub fn drift(&mut self, dt: f32) {
+        self.positions
+            .iter_mut()
+            .zip(&self.velocities)
+            .for_each(|(x, &v)| *x += v * dt);
+    }
+
+    pub fn kinetic_energy(&self) -> f32 {
+        self.velocities
+            .iter()
+            .zip(&self.masses)
+            .map(|(v, m)| 0.5 * m * v.mag_sq())
+            .sum()
+    }
+
+    pub fn potential_energy(&self, forces: &dyn ForceProvider) -> f32 {
+        let potentials = forces.potentials(&self.positions, &self.masses);
+
+        // Every pair is counted once from each side.
+        0.5 * potentials
+            .iter()
+            .zip(&self.masses)
+            .map(|(p, m)| p * m)
+            .sum::<f32>()
+    }
+
+    pub fn total_energy(&self, forces: &dyn ForceProvider) -> f32 {
+        self.kinetic_energy() + self.potential_energy(forces)
+    }
+}
+
+/// Particle system advanced in time by an [`Integrator`], using an [`Octree`] rebuilt at every
+/// force evaluation.
+///
+/// [`Octree`]: crate::octtree::Octree
+#[derive(Debug)]
+pub struct Simulation {
+    pub particles: Particles,
+    pub time: f32,
+    pub forces: TreeForces,
+    pub integrator: Box<dyn Integrator>,
+}
+
+impl Simulation {
+    /// Creates a simulation stepped with the kick-drift-kick leapfrog ([`VelocityVerlet`]).
+    pub fn new(
+        positions: Vec<Vec3>,
+        velocities: Vec<Vec3>,
+        masses: Vec<f32>,
+        theta: f32,
+        softening: f32,
+    ) -> Self {
+        let forces = TreeForces::new(theta, softening);
+
+        Self {
+            particles: Particles::new(positions, velocities, masses, &forces),
+            time: 0.,
+            forces,
+            integrator: Box::new(VelocityVerlet),
+        }
+    }
+
+    pub fn with_integrator(mut self, integrator: Box<dyn Integrator>) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    /// Recomputes the accelerations, e.g. after changing `forces` or the particles.
+    pub fn compute_accelerations(&mut self) {
+        self.particles.compute_accelerations(&self.forces);
+    }
+
+    /// Advances the system by `dt`.
+    pub fn step(&mut self, dt: f32) {
+        self.integrator.step(&mut self.particles, &self.forces, dt);
+        self.time += dt;
+    }
+
+    /// Advances the system by `n_steps` steps of `dt`.
+    pub fn run(&mut self, n_steps: usize, dt: f32) {
+        (0..n_steps).for_each(|_| self.step(dt));
+    }
+
+    /// Advances the system with individual timesteps to the next synchronization point of
+    /// `blocks`, bypassing `integrator`.
+    pub fn step_blocks(&mut self, blocks: &mut BlockTimesteps) {
+        self.time += blocks.step(&mut self.particles, &self.forces);
+    }
+
+    pub fn total_energy(&self) -> f32 {
+        self.particles.total_energy(&self.forces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Simulation;
+    use std::f32::consts::PI;
+    use ultraviolet::Vec3;
+
+    #[test]
+    fn test_circular_orbit() {
+        // Equal masses at unit separation orbit their common centre with period `2 pi / sqrt(2)`.
+        let positions = vec![Vec3::new(-0.5, 0., 0.), Vec3::new(0.5, 0., 0.)];
+        let speed = 0.5f32.sqrt();
+        let velocities = vec![Vec3::new(0., -speed, 0.), Vec3::new(0., speed, 0.)];
+
+        let mut sim = Simulation::new(positions.clone(), velocities, vec![1., 1.], 0.5, 0.);
+        let initial_energy = sim.total_energy();
+
+        let n_steps = 1000;
+        sim.run(n_steps, 2. * PI / 2f32.sqrt() / n_steps as f32);
+
+        assert!((sim.total_energy() - initial_energy).abs() < 1e-4 * initial_energy.abs());
+        sim.particles
+            .positions
+            .iter()
+            .zip(&positions)
+            .for_each(|(x, x0)| assert!((*x - *x0).mag() < 1e-2, "{:?} != {:?}", x, x0));
+    }
+}
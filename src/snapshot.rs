@@ -0,0 +1,242 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use ultraviolet::Vec3;
+
+use crate::simulation::Simulation;
+
+/// First bytes of every snapshot file.
+pub const MAGIC: [u8; 8] = *b"BHSNAP\0\0";
+/// Version of the format written by [`Snapshot::write`].
+pub const VERSION: u32 = 1;
+/// Length in bytes of the header preceding the particle data.
+pub const HEADER_LEN: usize = 36;
+
+/// Particle state at one instant, for post-processing and visualization outside the simulation.
+///
+/// Snapshots are stored in a binary format, with every value little-endian. The header is
+///
+/// | Offset | Type      | Field                                        |
+/// |--------|-----------|----------------------------------------------|
+/// | 0      | `[u8; 8]` | [`MAGIC`], `BHSNAP` padded with zero bytes   |
+/// | 8      | `u32`     | Format version, [`VERSION`]                  |
+/// | 12     | `u64`     | Number of particles `n`                      |
+/// | 20     | `f64`     | Simulation time                              |
+/// | 28     | `f32`     | Opening angle of the tree                    |
+/// | 32     | `f32`     | Softening length                             |
+///
+/// followed by one block per field, in struct-of-arrays order: `n` `u64` IDs, `n` positions and
+/// `n` velocities as `x, y, z` triples of `f32`, and `n` `f32` masses.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub time: f64,
+    pub theta: f32,
+    pub softening: f32,
+    pub ids: Vec<u64>,
+    pub positions: Vec<Vec3>,
+    pub velocities: Vec<Vec3>,
+    pub masses: Vec<f32>,
+}
+
+impl Snapshot {
+    /// Captures the current state of `simulation`, with the particles' indices as their IDs.
+    pub fn from_simulation(simulation: &Simulation) -> Self {
+        let particles = &simulation.particles;
+
+        Self {
+            time: simulation.time as f64,
+            theta: simulation.forces.theta,
+            softening: simulation.forces.softening,
+            ids: (0..particles.len() as u64).collect(),
+            positions: particles.positions.clone(),
+            velocities: particles.velocities.clone(),
+            masses: particles.masses.clone(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Writes the snapshot in the format described in [`Snapshot`].
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let n = self.len();
+        assert!(
+            self.positions.len() == n && self.velocities.len() == n && self.masses.len() == n,
+            "Lengths of the snapshot's fields differ"
+        );
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(n as u64).to_le_bytes())?;
+        writer.write_all(&self.time.to_le_bytes())?;
+        writer.write_all(&self.theta.to_le_bytes())?;
+        writer.write_all(&self.softening.to_le_bytes())?;
+
+        self.ids
+            .iter()
+            .try_for_each(|id| writer.write_all(&id.to_le_bytes()))?;
+        [&self.positions, &self.velocities]
+            .into_iter()
+            .flat_map(|vectors| vectors.iter().flat_map(|v| [v.x, v.y, v.z]))
+            .chain(self.masses.iter().copied())
+            .try_for_each(|x| writer.write_all(&x.to_le_bytes()))?;
+        writer.flush()
+    }
+
+    /// Reads a snapshot written by [`Snapshot::write`]. Fails with [`io::ErrorKind::InvalidData`]
+    /// if the data is not a snapshot or has an unsupported version, and with
+    /// [`io::ErrorKind::UnexpectedEof`] if it is truncated.
+    pub fn read(mut reader: impl Read) -> io::Result<Self> {
+        let magic: [u8; 8] = read_bytes(&mut reader)?;
+        if magic != MAGIC {
+            return Err(invalid_data("Not a snapshot".to_owned()));
+        }
+        let version = u32::from_le_bytes(read_bytes(&mut reader)?);
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "Unsupported snapshot version {version}"
+            )));
+        }
+
+        let n = u64::from_le_bytes(read_bytes(&mut reader)?);
+        let n = usize::try_from(n).map_err(|_| invalid_data(format!("Too many particles {n}")))?;
+        let time = f64::from_le_bytes(read_bytes(&mut reader)?);
+        let theta = f32::from_le_bytes(read_bytes(&mut reader)?);
+        let softening = f32::from_le_bytes(read_bytes(&mut reader)?);
+
+        // Fields are read element by element, so a corrupt count fails at the end of the data
+        // instead of allocating.
+        let read_f32 = |reader: &mut _| read_bytes(reader).map(f32::from_le_bytes);
+        let ids = (0..n)
+            .map(|_| read_bytes(&mut reader).map(u64::from_le_bytes))
+            .collect::<io::Result<_>>()?;
+        let read_vectors = |reader: &mut _| {
+            (0..n)
+                .map(|_| {
+                    Ok(Vec3::new(
+                        read_f32(reader)?,
+                        read_f32(reader)?,
+                        read_f32(reader)?,
+                    ))
+                })
+                .collect::<io::Result<Vec<_>>>()
+        };
+        let positions = read_vectors(&mut reader)?;
+        let velocities = read_vectors(&mut reader)?;
+        let masses = (0..n)
+            .map(|_| read_f32(&mut reader))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            time,
+            theta,
+            softening,
+            ids,
+            positions,
+            velocities,
+            masses,
+        })
+    }
+
+    /// Saves the snapshot to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    /// Loads a snapshot saved to `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Snapshot, HEADER_LEN, MAGIC};
+    use crate::{initial_conditions, simulation::Simulation};
+    use rand::prelude::*;
+    use std::io::ErrorKind;
+
+    fn simulation() -> Simulation {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (positions, velocities, masses) = initial_conditions::plummer(100, &mut rng);
+        let mut sim = Simulation::new(positions, velocities, masses, 0.5, 0.01);
+        sim.run(3, 0.01);
+        sim
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let sim = simulation();
+        let snapshot = Snapshot::from_simulation(&sim);
+        assert_eq!(snapshot.len(), 100);
+        assert_eq!(snapshot.time, sim.time as f64);
+        assert_eq!(snapshot.ids[99], 99);
+
+        let mut bytes = Vec::new();
+        snapshot.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), HEADER_LEN + 100 * (8 + 12 + 12 + 4));
+        assert_eq!(bytes[..8], MAGIC);
+        assert_eq!(bytes[8..12], [1, 0, 0, 0]);
+        assert_eq!(bytes[12..20], [100, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bytes[28..32], 0.5f32.to_le_bytes());
+        assert_eq!(
+            bytes[HEADER_LEN + 800..HEADER_LEN + 804],
+            sim.particles.positions[0].x.to_le_bytes()
+        );
+        assert_eq!(Snapshot::read(&bytes[..]).unwrap(), snapshot);
+
+        let path = std::env::temp_dir().join(format!("snapshot_{}.snap", std::process::id()));
+        snapshot.save(&path).unwrap();
+        let loaded = Snapshot::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), snapshot);
+
+        let mut empty = Vec::new();
+        Snapshot::default().write(&mut empty).unwrap();
+        assert_eq!(empty.len(), HEADER_LEN);
+        assert_eq!(Snapshot::read(&empty[..]).unwrap(), Snapshot::default());
+    }
+
+    #[test]
+    fn test_invalid_snapshots() {
+        let mut bytes = Vec::new();
+        Snapshot::from_simulation(&simulation())
+            .write(&mut bytes)
+            .unwrap();
+
+        let kind = |bytes: &[u8]| Snapshot::read(bytes).unwrap_err().kind();
+        assert_eq!(kind(&bytes[..bytes.len() - 1]), ErrorKind::UnexpectedEof);
+        assert_eq!(kind(&bytes[..HEADER_LEN - 1]), ErrorKind::UnexpectedEof);
+
+        let mut version = bytes.clone();
+        version[8] = 2;
+        assert_eq!(kind(&version), ErrorKind::InvalidData);
+
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert_eq!(kind(&magic), ErrorKind::InvalidData);
+
+        // A corrupt count runs out of data rather than allocating.
+        let mut count = bytes;
+        count[12..20].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(Snapshot::read(&count[..]).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+use crate::real::{Real, Wide};
+
+/// Ratio of the support radius of the [`Softening::Spline`] kernel to its softening length.
+const SPLINE_SUPPORT: f64 = 2.8;
+
+/// Softening kernel replacing the point mass potential `-m / r` at small separations, so close
+/// encounters give bounded forces.
+///
+/// Every kernel is parameterised by a softening length `h`. For a pair of particles this is the
+/// larger of their softening lengths, and all kernels have the potential `-m / h` at zero
+/// separation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Softening {
+    /// The unsoftened potential `-m / r`, ignoring the softening length.
+    None,
+    /// The Plummer potential `-m / sqrt(r^2 + h^2)`, softening forces at all separations.
+    #[default]
+    Plummer,
+    /// The cubic spline kernel of GADGET (Springel et al. 2001) with support radius `2.8 h`,
+    /// which is exactly Newtonian beyond the support radius.
+    Spline,
+}
+
+impl Softening {
+    /// Look up a kernel by its lowercase name, e.g. from the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Softening::None),
+            "plummer" => Some(Softening::Plummer),
+            "spline" => Some(Softening::Spline),
+            _ => None,
+        }
+    }
+
+    /// Softened `1 / r` and `1 / r^3` at squared separation `r_sq` for softening length `h`, so a
+    /// mass `m` at displacement `d` has potential `-m / r` and acceleration `m d / r^3`.
+    pub fn factors<S: Real>(self, r_sq: S, h: S) -> (S, S) {
+        let newtonian = |r_sq: S| {
+            let inv_r = S::ONE / r_sq.sqrt();
+            (inv_r, inv_r * inv_r * inv_r)
+        };
+
+        match self {
+            Softening::None => newtonian(r_sq),
+            Softening::Plummer => newtonian(r_sq + h * h),
+            Softening::Spline => {
+                let inv_h = S::ONE / (h * S::from_f64(SPLINE_SUPPORT));
+                let u = r_sq.sqrt() * inv_h;
+                let c = S::from_f64;
+
+                if u < c(0.5) {
+                    spline_inner(u, inv_h, c)
+                } else if u < c(1.) {
+                    spline_outer(u, inv_h, c)
+                } else {
+                    newtonian(r_sq)
+                }
+            }
+        }
+    }
+
+    /// [`Softening::factors`] for each lane.
+    pub(crate) fn lane_factors<S: Real>(self, r_sq: S::Wide, h: S::Wide) -> (S::Wide, S::Wide) {
+        let one = S::Wide::splat(S::ONE);
+        let newtonian = |r_sq: S::Wide| {
+            let inv_r = one / r_sq.sqrt();
+            (inv_r, inv_r * inv_r * inv_r)
+        };
+
+        match self {
+            Softening::None => newtonian(r_sq),
+            Softening::Plummer => newtonian(r_sq + h * h),
+            Softening::Spline => {
+                let inv_h = one / (h * S::Wide::splat(S::from_f64(SPLINE_SUPPORT)));
+                let u = r_sq.sqrt() * inv_h;
+                let c = |x| S::Wide::splat(S::from_f64(x));
+
+                let inner = spline_inner(u, inv_h, c);
+                let outer = spline_outer(u, inv_h, c);
+                let far = newtonian(r_sq);
+
+                // Lanes are picked by mask, so the infinities of unused branches never leak.
+                let (is_inner, is_outer) = (u.cmp_lt(c(0.5)), u.cmp_lt(c(1.)));
+                let pick = |inner, outer, far| is_inner.blend(inner, is_outer.blend(outer, far));
+                (pick(inner.0, outer.0, far.0), pick(inner.1, outer.1, far.1))
+            }
+        }
+    }
+}
+
+/// Spline kernel factors for `u = r / (2.8 h) < 1 / 2`.
+fn spline_inner<T>(u: T, inv_h: T, c: impl Fn(f64) -> T) -> (T, T)
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Output = T>,
+{
+    let (u2, u3) = (u * u, u * u * u);
+    let pot = c(14. / 5.) - c(16. / 3.) * u2 + c(48. / 5.) * u2 * u2 - c(32. / 5.) * u2 * u3;
+    let force = c(32. / 3.) - c(192. / 5.) * u2 + c(32.) * u3;
+    (pot * inv_h, force * inv_h * inv_h * inv_h)
+}
+
+/// Spline kernel factors for `1 / 2 <= u = r / (2.8 h) < 1`.
+fn spline_outer<T>(u: T, inv_h: T, c: impl Fn(f64) -> T) -> (T, T)
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Output = T> + Div<Output = T>,
+{
+    let (u2, u3) = (u * u, u * u * u);
+    let pot = c(16. / 5.) - c(1.) / (c(15.) * u) - c(32. / 3.) * u2 + c(16.) * u3
+        - c(48. / 5.) * u2 * u2
+        + c(32. / 15.) * u2 * u3;
+    let force =
+        c(64. / 3.) - c(48.) * u + c(192. / 5.) * u2 - c(32. / 3.) * u3 - c(1.) / (c(15.) * u3);
+    (pot * inv_h, force * inv_h * inv_h * inv_h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Softening;
+    use crate::real::Wide;
+    use ultraviolet::f32x8;
+
+    #[test]
+    fn test_softening_kernels() {
+        let h = 0.1f64;
+        for kernel in [Softening::None, Softening::Plummer, Softening::Spline] {
+            // Newtonian far away, and continuous across the spline's branches.
+            let (pot, force) = kernel.factors(100f64, h);
+            assert!((pot - 0.1).abs() < 1e-5 && (force - 1e-3).abs() < 1e-6);
+
+            let mut previous = kernel.factors(1e-4f64, h);
+            (100..=10000).map(|i| i as f64 * 1e-4).for_each(|r| {
+                let (pot, force) = kernel.factors(r * r, h);
+                assert!((pot - previous.0).abs() < 0.05 * pot, "{:?} {}", kernel, r);
+                assert!(
+                    (force - previous.1).abs() < 0.05 * force,
+                    "{:?} {}",
+                    kernel,
+                    r
+                );
+                previous = (pot, force);
+            });
+        }
+
+        // Both softened kernels have potential -m / h at zero separation.
+        assert!((Softening::Plummer.factors(0f64, h).0 - 10.).abs() < 1e-9);
+        assert!((Softening::Spline.factors(0f64, h).0 - 10.).abs() < 1e-9);
+        assert_eq!(
+            Softening::Spline.factors(0.09f64, h),
+            Softening::None.factors(0.09, h)
+        );
+
+        // The SIMD kernels agree with the scalar ones.
+        let r_sq = [0., 0.01, 0.04, 0.06, 0.08, 0.1, 1., 4.];
+        for kernel in [Softening::None, Softening::Plummer, Softening::Spline] {
+            let (pot, force) = kernel.lane_factors::<f32>(f32x8::new(r_sq), f32x8::splat(0.1));
+            r_sq.iter().enumerate().skip(1).for_each(|(i, &r_sq)| {
+                let (p, f) = kernel.factors(r_sq, 0.1f32);
+                assert!((Wide::to_array(pot)[i] - p).abs() <= 1e-5 * p);
+                assert!((Wide::to_array(force)[i] - f).abs() <= 1e-4 * f);
+            });
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Particle distributions, error measures and checks shared by the tests of the solvers.
+
+use rand::prelude::*;
+use ultraviolet::Vec3;
+
+use crate::{
+    direct,
+    octtree::{Octree, OctreeOptions},
+    real::{Real, Vector},
+};
+
+/// `n` points uniformly distributed in the cube `[-1, 1]^3`.
+pub fn uniform(n: usize, rng: &mut StdRng) -> Vec<Vec3> {
+    (0..n)
+        .map(|_| Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 2. - Vec3::one())
+        .collect()
+}
+
+/// `n` points in five clusters at uniformly distributed centres.
+pub fn clustered(n: usize, rng: &mut StdRng) -> Vec<Vec3> {
+    let centers = uniform(5, rng);
+    (0..n)
+        .map(|i| {
+            // Radii spread over two decades give dense cores and sparse halos.
+            let radius = 0.01 * 100f32.powf(rng.gen::<f32>());
+            let dir = Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 2. - Vec3::one();
+            centers[i % centers.len()] + dir.normalized() * radius
+        })
+        .collect()
+}
+
+/// `n` masses uniformly distributed in `[0.5, 1.5)`.
+pub fn masses(n: usize, rng: &mut StdRng) -> Vec<f32> {
+    (0..n).map(|_| rng.gen_range(0.5..1.5)).collect()
+}
+
+/// Fields at each of `points` from a walk of `tree` per point, as opposed to the shared
+/// interaction lists of [`Octree::fields`].
+pub fn walk_fields<S: Real>(
+    tree: &Octree<S>,
+    points: &[S::Vector],
+    theta: S,
+    softening: S,
+) -> Vec<(S::Vector, S)> {
+    points
+        .iter()
+        .map(|&point| tree.field(point, theta, softening))
+        .collect()
+}
+
+/// Mean relative errors of the accelerations and potentials in `approx`.
+pub fn errors<S: Real>(exact: &[(S::Vector, S)], approx: &[(S::Vector, S)]) -> (S, S) {
+    let (acc, pot) = exact
+        .iter()
+        .zip(approx)
+        .map(|(e, a)| ((a.0 - e.0).mag() / e.0.mag(), (a.1 - e.1).abs() / e.1.abs()))
+        .fold((S::ZERO, S::ZERO), |sum, (acc, pot)| {
+            (sum.0 + acc, sum.1 + pot)
+        });
+    let n = S::from_f64(exact.len() as f64);
+    (acc / n, pot / n)
+}
+
+/// Checks `solve`, which evaluates the fields at all points of a tree built with `options`,
+/// after removing points from it: the fields cover every index the tree was built with, are zero
+/// at the removed indices, including the last, and match the direct sum elsewhere.
+pub fn check_after_remove_point(
+    options: OctreeOptions,
+    solve: impl Fn(&Octree) -> Vec<(Vec3, f32)>,
+) {
+    let mut rng = StdRng::seed_from_u64(2);
+    let points = uniform(500, &mut rng);
+    let mut masses = vec![1f32; points.len()];
+    let removed = [7, 100, 499];
+
+    let mut tree = Octree::construct_with(&points, &masses, options);
+    removed.iter().for_each(|&idx| {
+        tree.remove_point(idx, points[idx]).unwrap();
+        masses[idx] = 0.;
+    });
+    tree.compute();
+
+    let fields = solve(&tree);
+    assert_eq!(fields.len(), points.len());
+    let exact = direct::fields(&points, &masses, 1e-3);
+    (0..points.len()).for_each(|i| {
+        if removed.contains(&i) {
+            assert_eq!(fields[i], (Vec3::zero(), 0.));
+        } else {
+            assert!((fields[i].0 - exact[i].0).mag() < 1e-4 * exact[i].0.mag());
+        }
+    });
+}
@@ -0,0 +1,226 @@
+use crate::{forces::ForceProvider, simulation::Particles};
+
+/// Hierarchical power-of-two block timesteps.
+///
+/// A particle on level `k` is stepped with `dt_max / 2^k` by a kick-drift-kick leapfrog, where
+/// the level is chosen from the criterion `dt = eta * sqrt(softening / |a|)`. Every substep
+/// drifts all particles but evaluates forces only for the particles whose step ends there, and
+/// substeps where no step ends evaluate no forces at all.
+#[derive(Clone, Debug)]
+pub struct BlockTimesteps {
+    pub dt_max: f32,
+    pub max_level: u32,
+    pub eta: f32,
+    pub softening: f32,
+    levels: Vec<u32>,
+    force_evaluations: usize,
+}
+
+impl BlockTimesteps {
+    pub fn new(dt_max: f32, max_level: u32, eta: f32, softening: f32) -> Self {
+        Self {
+            dt_max,
+            max_level,
+            eta,
+            softening,
+            levels: Vec::new(),
+            force_evaluations: 0,
+        }
+    }
+
+    /// Current level of each particle, empty before the first step.
+    pub fn levels(&self) -> &[u32] {
+        &self.levels
+    }
+
+    /// Number of single-particle force evaluations performed so far.
+    pub fn force_evaluations(&self) -> usize {
+        self.force_evaluations
+    }
+
+    /// Advances `particles` by `dt_max`, ending at a synchronization point where all positions
+    /// and velocities refer to the same time. Returns the elapsed time.
+    pub fn step(&mut self, particles: &mut Particles, forces: &dyn ForceProvider) -> f32 {
+        if self.levels.len() != particles.len() {
+            self.levels = (0..particles.len())
+                .map(|i| self.level_for(particles, i))
+                .collect();
+        }
+
+        let n_ticks = 1usize << self.max_level;
+        let dt_min = self.dt_max / n_ticks as f32;
+
+        for tick in 0..n_ticks {
+            // Opening half kick for every particle starting its step at this tick.
+            (0..particles.len())
+                .filter(|&i| tick % self.ticks(self.levels[i]) == 0)
+                .for_each(|i| {
+                    let dt = self.dt(self.levels[i]);
+                    particles.velocities[i] += particles.accelerations[i] * (dt / 2.);
+                });
+
+            particles.drift(dt_min);
+
+            let next = tick + 1;
+            let active: Vec<usize> = (0..particles.len())
+                .filter(|&i| next % self.ticks(self.levels[i]) == 0)
+                .collect();
+            if active.is_empty() {
+                continue;
+            }
+
+            let accelerations =
+                forces.accelerations_of(&particles.positions, &particles.masses, &active);
+            self.force_evaluations += active.len();
+
+            active.iter().zip(accelerations).for_each(|(&i, a)| {
+                let dt = self.dt(self.levels[i]);
+                particles.accelerations[i] = a;
+                particles.velocities[i] += a * (dt / 2.);
+
+                // Moving to a longer step is only allowed where that step's blocks line up.
+                let mut level = self.level_for(particles, i);
+                while next % self.ticks(level) != 0 {
+                    level += 1;
+                }
+                self.levels[i] = level;
+            });
+        }
+
+        self.dt_max
+    }
+
+    fn level_for(&self, particles: &Particles, i: usize) -> u32 {
+        let acc = particles.accelerations[i].mag();
+        if acc == 0. {
+            return 0;
+        }
+
+        let dt = self.eta * (self.softening / acc).sqrt();
+        ((self.dt_max / dt).log2().ceil().max(0.) as u32).min(self.max_level)
+    }
+
+    fn ticks(&self, level: u32) -> usize {
+        1 << (self.max_level - level)
+    }
+
+    fn dt(&self, level: u32) -> f32 {
+        self.dt_max / (1u32 << level) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlockTimesteps;
+    use crate::{
+        forces::{DirectForces, ForceProvider},
+        integrator::{Integrator, VelocityVerlet},
+        simulation::Particles,
+    };
+    use std::cell::Cell;
+    use ultraviolet::Vec3;
+
+    /// Counts the calls to [`ForceProvider::accelerations_of`] of the direct forces it wraps.
+    struct Counting(DirectForces, Cell<usize>);
+
+    impl ForceProvider for Counting {
+        fn accelerations(&self, positions: &[Vec3], masses: &[f32]) -> Vec<Vec3> {
+            self.0.accelerations(positions, masses)
+        }
+
+        fn potentials(&self, positions: &[Vec3], masses: &[f32]) -> Vec<f32> {
+            self.0.potentials(positions, masses)
+        }
+
+        fn accelerations_of(
+            &self,
+            positions: &[Vec3],
+            masses: &[f32],
+            targets: &[usize],
+        ) -> Vec<Vec3> {
+            assert!(!targets.is_empty());
+            self.1.set(self.1.get() + 1);
+            self.0.accelerations_of(positions, masses, targets)
+        }
+    }
+
+    /// A tight binary orbited by two distant, slow particles.
+    fn hierarchical_system(forces: &dyn ForceProvider) -> Particles {
+        let binary_speed = 0.5 * (2. / 0.2f32).sqrt();
+        let outer_speed = (2. / 100f32).sqrt();
+
+        Particles::new(
+            vec![
+                Vec3::new(-0.1, 0., 0.),
+                Vec3::new(0.1, 0., 0.),
+                Vec3::new(100., 0., 0.),
+                Vec3::new(-100., 0., 0.),
+            ],
+            vec![
+                Vec3::new(0., -binary_speed, 0.),
+                Vec3::new(0., binary_speed, 0.),
+                Vec3::new(0., 0., outer_speed),
+                Vec3::new(0., 0., -outer_speed),
+            ],
+            vec![1., 1., 1e-3, 1e-3],
+            forces,
+        )
+    }
+
+    #[test]
+    fn test_block_timesteps() {
+        let forces = DirectForces::new(0.);
+        let mut particles = hierarchical_system(&forces);
+        let mut reference = particles.clone();
+        let initial_energy = particles.total_energy(&forces);
+
+        let max_level = 8;
+        let mut blocks = BlockTimesteps::new(0.5, max_level, 0.15, 0.01);
+        let n_blocks = 10;
+        (0..n_blocks).for_each(|_| {
+            blocks.step(&mut particles, &forces);
+        });
+
+        let levels = blocks.levels();
+        assert!(levels[0] >= 6 && levels[1] >= 6, "{:?}", levels);
+        assert_eq!(&levels[2..], &[0, 0], "{:?}", levels);
+
+        let shared_evaluations = (n_blocks * particles.len()) << max_level;
+        assert!(blocks.force_evaluations() < shared_evaluations * 3 / 4);
+
+        let energy = particles.total_energy(&forces);
+        assert!((energy - initial_energy).abs() < 1e-3 * initial_energy.abs());
+
+        // The outer particles should follow the same path as with a shared, smallest step.
+        let dt_min = blocks.dt_max / (1 << max_level) as f32;
+        (0..n_blocks << max_level)
+            .for_each(|_| VelocityVerlet.step(&mut reference, &forces, dt_min));
+        (2..4).for_each(|i| {
+            let (x, x_ref) = (particles.positions[i], reference.positions[i]);
+            assert!((x - x_ref).mag() < 1e-3, "{:?} != {:?}", x, x_ref);
+        });
+    }
+
+    #[test]
+    fn test_idle_substeps_evaluate_no_forces() {
+        let forces = Counting(DirectForces::new(0.), Cell::new(0));
+        let mut particles = hierarchical_system(&forces);
+
+        // The subset matches the full evaluation.
+        let all = forces.accelerations(&particles.positions, &particles.masses);
+        let subset = forces.accelerations_of(&particles.positions, &particles.masses, &[3, 1]);
+        assert_eq!(subset, vec![all[3], all[1]]);
+
+        // Only the binary's substeps, and the block boundaries for the outer pair, need forces.
+        let mut blocks = BlockTimesteps::new(0.5, 10, 0.15, 0.01);
+        forces.1.set(0);
+        blocks.step(&mut particles, &forces);
+        let calls = forces.1.get();
+        assert!(
+            (1 << 6..1 << 10).contains(&calls),
+            "{} {:?}",
+            calls,
+            blocks.levels()
+        );
+    }
+}